@@ -0,0 +1,296 @@
+//! Compare the latency of typical town queries on the raw tables, joined in every query, with the
+//! same queries on the pre-joined `towns_joined` table. Run with `cargo bench --bench queries`.
+//!
+//! The fixture world is generated with a fixed seed and has the size of a full grepolis world:
+//! about 10 000 islands, 70 000 towns, 15 000 players and 800 alliances.
+
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[allow(dead_code)]
+#[path = "../src/model/offset_data.rs"]
+mod offset_data;
+#[allow(dead_code)]
+#[path = "../src/model/towns_joined.rs"]
+mod towns_joined;
+
+const ISLANDS: u32 = 10_000;
+const PLAYERS: u32 = 15_000;
+const ALLIANCES: u32 = 800;
+const ITERATIONS: usize = 20;
+
+/// How every query looked before the towns were pre-joined
+const LEGACY_SELECTION: &str = "towns.*, offsets.offset_x, offsets.offset_y, players.name, \
+    alliances.name, player_activity.hours_inactive, player_activity.last_growth";
+const LEGACY_TABLES: &str = "from \n\
+    towns \n\
+    LEFT JOIN islands ON (towns.island_x = islands.x AND towns.island_y = islands.y) \n\
+    LEFT JOIN offsets ON (towns.slot_number = offsets.slot_number) \n\
+    LEFT JOIN players ON (towns.player_id = players.player_id) \n\
+    LEFT JOIN alliances ON (players.alliance_id = alliances.alliance_id) \n\
+    LEFT JOIN town_stats ON (towns.town_id = town_stats.town_id) \n\
+    LEFT JOIN player_stats ON (towns.player_id = player_stats.player_id) \n\
+    LEFT JOIN alliance_stats ON (players.alliance_id = alliance_stats.alliance_id) \n\
+    LEFT JOIN town_changes ON (towns.town_id = town_changes.town_id) \n\
+    LEFT JOIN player_changes ON (towns.player_id = player_changes.player_id) \n\
+    LEFT JOIN player_activity ON (towns.player_id = player_activity.player_id)";
+const LEGACY_JOIN_CONDITION: &str = "islands.type = offsets.type AND";
+
+struct Query {
+    name: &'static str,
+    legacy: String,
+    joined: String,
+}
+
+impl Query {
+    fn towns(name: &'static str, legacy_filter: &str, joined_filter: &str) -> Self {
+        Self {
+            name,
+            legacy: format!(
+                "SELECT {LEGACY_SELECTION} {LEGACY_TABLES} \nWHERE {LEGACY_JOIN_CONDITION} ({legacy_filter})"
+            ),
+            joined: format!(
+                "SELECT {} {} \nWHERE ({joined_filter})",
+                towns_joined::TOWN_SELECTION,
+                towns_joined::TOWN_TABLES
+            ),
+        }
+    }
+
+    fn names(name: &'static str, legacy: (&str, &str), joined: (&str, &str)) -> Self {
+        Self {
+            name,
+            legacy: format!(
+                "SELECT DISTINCT {0} {LEGACY_TABLES} \nWHERE {LEGACY_JOIN_CONDITION} ({1}) \nORDER BY LOWER({0})",
+                legacy.0, legacy.1
+            ),
+            joined: format!(
+                "SELECT DISTINCT {0} {1} \nWHERE ({2}) \nORDER BY LOWER({0})",
+                joined.0,
+                towns_joined::TOWN_TABLES,
+                joined.1
+            ),
+        }
+    }
+}
+
+fn create_world(connection: &mut rusqlite::Connection) -> rusqlite::Result<()> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "CREATE TABLE players(player_id INTEGER UNIQUE PRIMARY KEY, name TEXT UNIQUE,
+            alliance_id INTEGER, points INTEGER, rank INTEGER, towns INTEGER);
+        CREATE TABLE alliances(alliance_id INTEGER UNIQUE PRIMARY KEY, name TEXT UNIQUE,
+            points INTEGER, towns INTEGER, members INTEGER, rank INTEGER);
+        CREATE TABLE towns(town_id INTEGER UNIQUE PRIMARY KEY, player_id INTEGER, name TEXT,
+            island_x INTEGER, island_y INTEGER, slot_number INTEGER, points INTEGER);
+        CREATE TABLE islands(island_id INTEGER UNIQUE PRIMARY KEY, x INTEGER, y INTEGER,
+            type INTEGER, towns INTEGER, ressource_plus TEXT, ressource_minus TEXT);
+        CREATE TABLE offsets(type INTEGER NOT NULL, offset_x INTEGER NOT NULL,
+            offset_y INTEGER NOT NULL, slot_number INTEGER NOT NULL,
+            PRIMARY KEY (type, slot_number));
+        CREATE TABLE town_stats(town_id INTEGER UNIQUE PRIMARY KEY, ocean INTEGER,
+            player_towns_on_island INTEGER, alliance_ocean_share INTEGER);
+        CREATE TABLE player_stats(player_id INTEGER UNIQUE PRIMARY KEY,
+            average_town_points INTEGER);
+        CREATE TABLE alliance_stats(alliance_id INTEGER UNIQUE PRIMARY KEY,
+            points_per_member INTEGER);
+        CREATE TEMP TABLE town_changes(town_id INTEGER UNIQUE PRIMARY KEY, owner_changed INTEGER,
+            founded INTEGER, became_ghost INTEGER);
+        CREATE TEMP TABLE player_changes(player_id INTEGER UNIQUE PRIMARY KEY,
+            points_change INTEGER, alliance_changed INTEGER);
+        CREATE TEMP TABLE player_activity(player_id INTEGER UNIQUE PRIMARY KEY,
+            hours_inactive INTEGER, last_growth TEXT);",
+    )?;
+
+    let mut slots_per_type: Vec<(u32, u32)> = Vec::new();
+    {
+        let mut statement = transaction.prepare("INSERT INTO offsets VALUES(?1, ?2, ?3, ?4)")?;
+        for line in offset_data::OFFSET_DATA.lines() {
+            let values: Vec<u32> = line.split(',').map(|v| v.parse().unwrap()).collect();
+            statement.execute((values[0], values[1], values[2], values[3]))?;
+            match slots_per_type.last_mut() {
+                Some((island_type, slots)) if *island_type == values[0] => *slots += 1,
+                _ => slots_per_type.push((values[0], 1)),
+            }
+        }
+    }
+
+    {
+        let mut statement =
+            transaction.prepare("INSERT INTO alliances VALUES(?1, ?2, ?3, ?4, ?5, ?6)")?;
+        for alliance_id in 1..=ALLIANCES {
+            statement.execute((
+                alliance_id,
+                format!("Alliance {alliance_id}"),
+                rng.gen_range(0..20_000_000),
+                rng.gen_range(0..2_000),
+                rng.gen_range(1..100),
+                alliance_id,
+            ))?;
+        }
+    }
+
+    {
+        let mut statement =
+            transaction.prepare("INSERT INTO players VALUES(?1, ?2, ?3, ?4, ?5, ?6)")?;
+        for player_id in 1..=PLAYERS {
+            let alliance_id = rng.gen_bool(0.7).then(|| rng.gen_range(1..=ALLIANCES));
+            statement.execute((
+                player_id,
+                format!("Player {player_id}"),
+                alliance_id,
+                rng.gen_range(0..500_000),
+                player_id,
+                rng.gen_range(1..50),
+            ))?;
+        }
+    }
+
+    {
+        let mut insert_island =
+            transaction.prepare("INSERT INTO islands VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+        let mut insert_town =
+            transaction.prepare("INSERT INTO towns VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+        let resources = ["wood", "stone", "iron"];
+        let mut town_id = 0;
+        for island_id in 0..ISLANDS {
+            // islands on a grid of 10 by 10 units, so their coordinates are unique
+            let x = (island_id % 100) * 10 + rng.gen_range(0..10);
+            let y = (island_id / 100) * 10 + rng.gen_range(0..10);
+            let (island_type, slots) = slots_per_type[rng.gen_range(0..slots_per_type.len())];
+            insert_island.execute((
+                island_id,
+                x,
+                y,
+                island_type,
+                slots,
+                resources[rng.gen_range(0..3)],
+                resources[rng.gen_range(0..3)],
+            ))?;
+            for slot_number in 0..slots {
+                if rng.gen_bool(0.5) {
+                    continue;
+                }
+                town_id += 1;
+                let player_id = rng.gen_bool(0.95).then(|| rng.gen_range(1..=PLAYERS));
+                insert_town.execute((
+                    town_id,
+                    player_id,
+                    format!("Town {town_id}"),
+                    x,
+                    y,
+                    slot_number,
+                    rng.gen_range(100..13_000),
+                ))?;
+            }
+        }
+    }
+
+    transaction.execute_batch(
+        "INSERT INTO town_stats SELECT town_id, (island_x / 100) * 10 + (island_y / 100), NULL, NULL FROM towns;
+        INSERT INTO player_stats SELECT player_id, AVG(points) FROM towns WHERE player_id IS NOT NULL GROUP BY player_id;
+        INSERT INTO alliance_stats SELECT alliance_id, points / members FROM alliances;",
+    )?;
+    transaction.execute(towns_joined::CREATE_TABLE, ())?;
+    transaction.execute(towns_joined::FILL_TABLE, ())?;
+    for index in towns_joined::CREATE_INDICES {
+        transaction.execute(index, ())?;
+    }
+    transaction.commit()
+}
+
+/// Median time and number of rows of a query
+fn measure(connection: &rusqlite::Connection, sql: &str) -> rusqlite::Result<(Duration, usize)> {
+    let mut statement = connection.prepare(sql)?;
+    let mut durations = Vec::with_capacity(ITERATIONS);
+    let mut rows = 0;
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        rows = statement.query([])?.mapped(|_row| Ok(())).count();
+        durations.push(start.elapsed());
+    }
+    durations.sort();
+    Ok((durations[ITERATIONS / 2], rows))
+}
+
+fn main() -> rusqlite::Result<()> {
+    let start = Instant::now();
+    let mut connection = rusqlite::Connection::open_in_memory()?;
+    create_world(&mut connection)?;
+    let town_count: usize =
+        connection.query_row("SELECT COUNT(*) FROM towns", [], |row| row.get(0))?;
+    println!(
+        "Created fixture world with {town_count} towns in {:?}",
+        start.elapsed()
+    );
+
+    let queries = [
+        Query::towns("all towns", "true", "true"),
+        Query::towns(
+            "ghost towns",
+            "towns.player_id IS NULL",
+            "towns.player_id IS NULL",
+        ),
+        Query::towns(
+            "player name",
+            "players.name = 'Player 1234'",
+            "towns.player_name = 'Player 1234'",
+        ),
+        Query::towns(
+            "alliance name",
+            "alliances.name = 'Alliance 123'",
+            "towns.alliance_name = 'Alliance 123'",
+        ),
+        Query::towns(
+            "player points",
+            "players.points >= 450000",
+            "towns.player_points >= 450000",
+        ),
+        Query::towns(
+            "two alliances, or",
+            "alliances.name = 'Alliance 1' OR alliances.name = 'Alliance 2'",
+            "towns.alliance_name = 'Alliance 1' OR towns.alliance_name = 'Alliance 2'",
+        ),
+        Query::towns(
+            "island",
+            "islands.x BETWEEN 500 AND 509 AND islands.y BETWEEN 500 AND 509",
+            "towns.island_x BETWEEN 500 AND 509 AND towns.island_y BETWEEN 500 AND 509",
+        ),
+        Query::names(
+            "drop down: players in alliance",
+            ("players.name", "alliances.name = 'Alliance 123'"),
+            ("towns.player_name", "towns.alliance_name = 'Alliance 123'"),
+        ),
+        Query::names(
+            "drop down: alliances with big players",
+            ("alliances.name", "players.points >= 450000"),
+            ("towns.alliance_name", "towns.player_points >= 450000"),
+        ),
+    ];
+
+    println!(
+        "{:<40} {:>8} {:>12} {:>12} {:>8}",
+        "query", "rows", "joins", "pre-joined", "speedup"
+    );
+    for query in &queries {
+        let (legacy_time, legacy_rows) = measure(&connection, &query.legacy)?;
+        let (joined_time, joined_rows) = measure(&connection, &query.joined)?;
+        assert_eq!(
+            legacy_rows, joined_rows,
+            "{}: both variants have to return the same rows",
+            query.name
+        );
+        println!(
+            "{:<40} {:>8} {:>12.3?} {:>12.3?} {:>7.1}x",
+            query.name,
+            joined_rows,
+            legacy_time,
+            joined_time,
+            legacy_time.as_secs_f64() / joined_time.as_secs_f64()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Compare the linear scans the map used for viewport culling and hover lookups with the same
+//! lookups on the spatial index. Run with `cargo bench --bench spatial`.
+//!
+//! The fixture world is generated with a fixed seed and has the size of a full grepolis world:
+//! about 10 000 islands with 70 000 towns.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+#[allow(dead_code)]
+#[path = "../src/spatial.rs"]
+mod spatial;
+
+use spatial::{Positioned, SpatialIndex};
+
+const ISLANDS: u32 = 10_000;
+const TOWNS_PER_ISLAND: u32 = 7;
+const ITERATIONS: usize = 50;
+/// how far the hover popup reaches, in world units
+const HOVER_REACH: f32 = 1.5;
+
+struct Town {
+    x: f32,
+    y: f32,
+}
+
+impl Positioned for Town {
+    fn position(&self) -> egui::Vec2 {
+        egui::vec2(self.x, self.y)
+    }
+}
+
+/// Towns on the coast of randomly placed islands, like in a real world
+fn create_world() -> Vec<Town> {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut towns = Vec::new();
+    for _ in 0..ISLANDS {
+        let island_x = rng.gen_range(0.0..995.0);
+        let island_y = rng.gen_range(0.0..995.0);
+        for _ in 0..TOWNS_PER_ISLAND {
+            towns.push(Town {
+                x: island_x + rng.gen_range(0.0..5.0),
+                y: island_y + rng.gen_range(0.0..5.0),
+            });
+        }
+    }
+    towns
+}
+
+/// Median time of a closure and what it returned
+fn measure<T>(mut function: impl FnMut() -> T) -> (Duration, T) {
+    let mut durations = Vec::with_capacity(ITERATIONS);
+    let mut result = function();
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        result = black_box(function());
+        durations.push(start.elapsed());
+    }
+    durations.sort();
+    (durations[ITERATIONS / 2], result)
+}
+
+/// How the map filtered the towns by the viewport
+fn linear_in_rect(towns: &[Town], rect: egui::Rect) -> usize {
+    towns
+        .iter()
+        .filter(|town| {
+            rect.left() < town.x
+                && town.x < rect.right()
+                && rect.top() < town.y
+                && town.y < rect.bottom()
+        })
+        .count()
+}
+
+/// How the map looked for the town under the mouse
+fn linear_nearest(towns: &[Town], position: egui::Pos2) -> Option<usize> {
+    towns
+        .iter()
+        .enumerate()
+        .map(|(index, town)| (index, position.distance(egui::pos2(town.x, town.y))))
+        .filter(|(_, distance)| *distance < HOVER_REACH)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(index, _)| index)
+}
+
+fn print_row(name: &str, count: usize, linear: Duration, indexed: Duration) {
+    println!(
+        "{:<40} {:>8} {:>12.3?} {:>12.3?} {:>7.1}x",
+        name,
+        count,
+        linear,
+        indexed,
+        linear.as_secs_f64() / indexed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let towns = create_world();
+    let (build_time, index) = measure(|| SpatialIndex::new(towns.iter().map(Positioned::position)));
+    println!(
+        "Created fixture world with {} towns, building the index takes {build_time:?}",
+        towns.len()
+    );
+
+    println!(
+        "{:<40} {:>8} {:>12} {:>12} {:>8}",
+        "lookup", "towns", "linear", "indexed", "speedup"
+    );
+    for (name, side) in [
+        ("viewport: whole world", 1000.0),
+        ("viewport: one ocean", 100.0),
+        ("viewport: a few islands", 20.0),
+    ] {
+        let rect =
+            egui::Rect::from_min_size(egui::pos2(450.0, 450.0), egui::Vec2::splat(side)).intersect(
+                egui::Rect::from_min_size(egui::Pos2::ZERO, egui::Vec2::splat(1000.0)),
+            );
+        let (linear_time, linear_count) = measure(|| linear_in_rect(&towns, rect));
+        let (indexed_time, indexed_count) = measure(|| index.in_rect(rect).count());
+        assert_eq!(
+            linear_count, indexed_count,
+            "{name}: both variants have to find the same towns"
+        );
+        print_row(name, indexed_count, linear_time, indexed_time);
+    }
+
+    let positions: Vec<egui::Pos2> = towns
+        .iter()
+        .step_by(towns.len() / 100)
+        .map(|town| egui::pos2(town.x + 0.3, town.y - 0.2))
+        .collect();
+    let (linear_time, linear_hits) = measure(|| {
+        positions
+            .iter()
+            .map(|position| linear_nearest(&towns, *position))
+            .collect::<Vec<_>>()
+    });
+    let (indexed_time, indexed_hits) = measure(|| {
+        positions
+            .iter()
+            .map(|position| {
+                index
+                    .nearest(*position, HOVER_REACH)
+                    .map(|(index, _)| index)
+            })
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(
+        linear_hits, indexed_hits,
+        "hover: both variants have to find the same towns"
+    );
+    print_row(
+        &format!("hover: {} positions", positions.len()),
+        indexed_hits.iter().flatten().count(),
+        linear_time,
+        indexed_time,
+    );
+}
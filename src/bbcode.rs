@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+use strum_macros::EnumIter;
+
+use crate::constraint::{Comparator, ConstraintType};
+use crate::emptyconstraint::EmptyConstraint;
+use crate::emptyselection::EmptyTownSelection;
+use crate::selection::{AndOr, Grouping};
+use crate::town::{Town, TownGroup};
+
+/// The ways a selection can be written as `BBCode` for the in-game forum and messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum BBCodeTemplate {
+    TownList,
+    ByPlayer,
+    ByDistance,
+    PointsTable,
+    Groups,
+}
+
+impl ToString for BBCodeTemplate {
+    fn to_string(&self) -> String {
+        match self {
+            BBCodeTemplate::TownList => t!("selection.bbcode.town_list"),
+            BBCodeTemplate::ByPlayer => t!("selection.bbcode.by_player"),
+            BBCodeTemplate::ByDistance => t!("selection.bbcode.by_distance"),
+            BBCodeTemplate::PointsTable => t!("selection.bbcode.points_table"),
+            BBCodeTemplate::Groups => t!("selection.bbcode.groups"),
+        }
+    }
+}
+
+fn town_tag(town: &Town) -> String {
+    format!("[town]{}[/town]", town.id)
+}
+
+fn player_tag(name: Option<&str>) -> String {
+    name.map_or_else(
+        || t!("selection.grouping.no_name"),
+        |name| format!("[player]{name}[/player]"),
+    )
+}
+
+fn ally_tag(name: Option<&str>) -> String {
+    name.map_or_else(
+        || t!("selection.grouping.no_name"),
+        |name| format!("[ally]{name}[/ally]"),
+    )
+}
+
+/// A forum table, the first row is the header
+fn table(header: &[String], rows: impl Iterator<Item = Vec<String>>) -> String {
+    let mut text = format!("[table]\n[**]{}[/**]\n", header.join("[||]"));
+    for row in rows {
+        let _ = writeln!(text, "[*]{}[/*]", row.join("[|]"));
+    }
+    text.push_str("[/table]");
+    text
+}
+
+fn distance(town: &Town, origin: (u16, u16)) -> f32 {
+    let dx = town.x - f32::from(origin.0);
+    let dy = town.y - f32::from(origin.1);
+    dx.hypot(dy)
+}
+
+impl BBCodeTemplate {
+    /// Sorting by distance needs a town or island to measure from
+    pub fn needs_origin(self) -> bool {
+        self == BBCodeTemplate::ByDistance
+    }
+
+    /// Only selections that are shown as players or alliances have groups
+    pub fn needs_groups(self) -> bool {
+        self == BBCodeTemplate::Groups
+    }
+
+    pub fn export(
+        self,
+        towns: &[Town],
+        grouping: Grouping,
+        groups: &[TownGroup],
+        origin: Option<(u16, u16)>,
+    ) -> String {
+        match self {
+            BBCodeTemplate::TownList => towns.iter().map(town_tag).collect::<Vec<_>>().join("\n"),
+            BBCodeTemplate::ByPlayer => {
+                let mut by_player: BTreeMap<Option<&str>, Vec<&Town>> = BTreeMap::new();
+                for town in towns {
+                    by_player
+                        .entry(town.player_name.as_deref())
+                        .or_default()
+                        .push(town);
+                }
+                by_player
+                    .into_iter()
+                    .map(|(player, towns)| {
+                        let mut text = format!("[b]{}[/b]\n", player_tag(player));
+                        for town in towns {
+                            let _ = writeln!(text, "{} ({})", town_tag(town), town.points);
+                        }
+                        text
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            BBCodeTemplate::ByDistance => {
+                let Some(origin) = origin else {
+                    return String::new();
+                };
+                let mut sorted: Vec<(f32, &Town)> = towns
+                    .iter()
+                    .map(|town| (distance(town, origin), town))
+                    .collect();
+                sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+                table(
+                    &[
+                        t!("selection.table.column_name"),
+                        t!("selection.table.column_player"),
+                        t!("selection.bbcode.distance"),
+                    ],
+                    sorted.into_iter().map(|(distance, town)| {
+                        vec![
+                            town_tag(town),
+                            player_tag(town.player_name.as_deref()),
+                            format!("{distance:.1}"),
+                        ]
+                    }),
+                )
+            }
+            BBCodeTemplate::PointsTable => {
+                let mut sorted: Vec<&Town> = towns.iter().collect();
+                sorted.sort_by_key(|town| std::cmp::Reverse(town.points));
+                table(
+                    &[
+                        t!("selection.table.column_name"),
+                        t!("selection.table.column_player"),
+                        t!("selection.table.column_alliance"),
+                        t!("selection.table.column_points"),
+                    ],
+                    sorted.into_iter().map(|town| {
+                        vec![
+                            town_tag(town),
+                            player_tag(town.player_name.as_deref()),
+                            ally_tag(town.alliance_name.as_deref()),
+                            format!("{}", town.points),
+                        ]
+                    }),
+                )
+            }
+            BBCodeTemplate::Groups => {
+                let tag = match grouping {
+                    Grouping::Towns => return String::new(),
+                    Grouping::Players => player_tag,
+                    Grouping::Alliances => ally_tag,
+                };
+                table(
+                    &[
+                        t!("selection.grouping.column_name"),
+                        t!("selection.grouping.column_players"),
+                        t!("selection.grouping.column_towns"),
+                        t!("selection.grouping.column_points"),
+                    ],
+                    groups.iter().map(|group| {
+                        vec![
+                            tag(group.name.as_deref()),
+                            format!("{}", group.players),
+                            format!("{}", group.towns),
+                            format!("{}", group.points),
+                        ]
+                    }),
+                )
+            }
+        }
+    }
+}
+
+/// One entry of a list of towns, players or alliances that was pasted into the forum or a chat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListEntry {
+    Town(String),
+    Player(String),
+    Alliance(String),
+}
+
+impl fmt::Display for ListEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListEntry::Town(id) => write!(f, "[town]{id}[/town]"),
+            ListEntry::Player(name) => write!(f, "[player]{name}[/player]"),
+            ListEntry::Alliance(name) => write!(f, "[ally]{name}[/ally]"),
+        }
+    }
+}
+
+impl ListEntry {
+    /// Read the `[town]`, `[player]` and `[ally]` tags of a text. A text without any of these
+    /// tags is read line by line: numbers are town ids, everything else is a player name.
+    pub fn parse_list(text: &str) -> Vec<Self> {
+        let tags = [
+            ("town", ListEntry::Town as fn(String) -> Self),
+            ("player", ListEntry::Player),
+            ("ally", ListEntry::Alliance),
+        ];
+        let mut tagged = Vec::new();
+        for (tag, entry) in tags {
+            let open = format!("[{tag}]");
+            let close = format!("[/{tag}]");
+            let mut rest = text;
+            let mut offset = 0;
+            while let Some(start) = rest.find(&open) {
+                let content_start = start + open.len();
+                let Some(length) = rest[content_start..].find(&close) else {
+                    break;
+                };
+                let content = rest[content_start..content_start + length].trim();
+                if !content.is_empty() {
+                    tagged.push((offset + start, entry(content.to_string())));
+                }
+                let end = content_start + length + close.len();
+                offset += end;
+                rest = &rest[end..];
+            }
+        }
+
+        let entries: Vec<Self> = if tagged.is_empty() {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| {
+                    if line.parse::<u32>().is_ok() {
+                        ListEntry::Town(line.to_string())
+                    } else {
+                        ListEntry::Player(line.to_string())
+                    }
+                })
+                .collect()
+        } else {
+            // keep the order of the text, no matter which kind of tag came first
+            tagged.sort_by_key(|(position, _)| *position);
+            tagged.into_iter().map(|(_, entry)| entry).collect()
+        };
+
+        let mut unique = Vec::with_capacity(entries.len());
+        for entry in entries {
+            if !unique.contains(&entry) {
+                unique.push(entry);
+            }
+        }
+        unique
+    }
+
+    pub fn to_constraint(&self) -> EmptyConstraint {
+        let (constraint_type, value) = match self {
+            ListEntry::Town(id) => (ConstraintType::TownID, id),
+            ListEntry::Player(name) => (ConstraintType::PlayerName, name),
+            ListEntry::Alliance(name) => (ConstraintType::AllianceName, name),
+        };
+        EmptyConstraint {
+            constraint_type,
+            comparator: Comparator::Equal,
+            value: value.clone(),
+        }
+    }
+
+    /// A selection that contains every town of the given entries
+    pub fn to_selection(name: String, entries: &[Self]) -> EmptyTownSelection {
+        EmptyTownSelection {
+            name,
+            constraints: entries.iter().map(Self::to_constraint).collect(),
+            constraint_join_mode: AndOr::Or,
+            ..EmptyTownSelection::default()
+        }
+    }
+}
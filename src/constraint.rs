@@ -166,14 +166,25 @@ pub enum ConstraintType {
     PlayerPoints,
     PlayerRank,
     PlayerTowns,
+    PlayerAverageTownPoints,
+    PlayerPointsChange,
+    PlayerAllianceChanged,
+    PlayerHoursInactive,
     AllianceName,
     AlliancePoints,
     AllianceTowns,
     AllianceMembers,
     AllianceRank,
+    AlliancePointsPerMember,
     TownID,
     TownName,
     TownPoints,
+    TownOcean,
+    TownPlayerTownsOnIsland,
+    TownAllianceOceanShare,
+    TownOwnerChanged,
+    TownFounded,
+    TownBecameGhost,
     IslandID,
     IslandX,
     IslandY,
@@ -191,14 +202,35 @@ impl ToString for ConstraintType {
             ConstraintType::PlayerPoints => t!("selection.constraint.player_points"),
             ConstraintType::PlayerRank => t!("selection.constraint.player_rank"),
             ConstraintType::PlayerTowns => t!("selection.constraint.player_towns"),
+            ConstraintType::PlayerAverageTownPoints => {
+                t!("selection.constraint.player_average_town_points")
+            }
+            ConstraintType::PlayerPointsChange => t!("selection.constraint.player_points_change"),
+            ConstraintType::PlayerAllianceChanged => {
+                t!("selection.constraint.player_alliance_changed")
+            }
+            ConstraintType::PlayerHoursInactive => t!("selection.constraint.player_hours_inactive"),
             ConstraintType::AllianceName => t!("selection.constraint.alliance_name"),
             ConstraintType::AlliancePoints => t!("selection.constraint.alliance_points"),
             ConstraintType::AllianceTowns => t!("selection.constraint.alliance_towns"),
             ConstraintType::AllianceMembers => t!("selection.constraint.alliance_members"),
             ConstraintType::AllianceRank => t!("selection.constraint.alliance_rank"),
+            ConstraintType::AlliancePointsPerMember => {
+                t!("selection.constraint.alliance_points_per_member")
+            }
             ConstraintType::TownID => t!("selection.constraint.town_id"),
             ConstraintType::TownName => t!("selection.constraint.town_name"),
             ConstraintType::TownPoints => t!("selection.constraint.town_points"),
+            ConstraintType::TownOcean => t!("selection.constraint.town_ocean"),
+            ConstraintType::TownPlayerTownsOnIsland => {
+                t!("selection.constraint.town_player_towns_on_island")
+            }
+            ConstraintType::TownAllianceOceanShare => {
+                t!("selection.constraint.town_alliance_ocean_share")
+            }
+            ConstraintType::TownOwnerChanged => t!("selection.constraint.town_owner_changed"),
+            ConstraintType::TownFounded => t!("selection.constraint.town_founded"),
+            ConstraintType::TownBecameGhost => t!("selection.constraint.town_became_ghost"),
             ConstraintType::IslandID => t!("selection.constraint.island_id"),
             ConstraintType::IslandX => t!("selection.constraint.island_x"),
             ConstraintType::IslandY => t!("selection.constraint.island_y"),
@@ -233,6 +265,18 @@ impl ConstraintType {
             | ConstraintType::IslandTowns
             | ConstraintType::IslandResMore
             | ConstraintType::IslandResLess => String::from("islands"),
+            ConstraintType::PlayerAverageTownPoints => String::from("player_stats"),
+            ConstraintType::AlliancePointsPerMember => String::from("alliance_stats"),
+            ConstraintType::TownOcean
+            | ConstraintType::TownPlayerTownsOnIsland
+            | ConstraintType::TownAllianceOceanShare => String::from("town_stats"),
+            ConstraintType::PlayerPointsChange | ConstraintType::PlayerAllianceChanged => {
+                String::from("player_changes")
+            }
+            ConstraintType::PlayerHoursInactive => String::from("player_activity"),
+            ConstraintType::TownOwnerChanged
+            | ConstraintType::TownFounded
+            | ConstraintType::TownBecameGhost => String::from("town_changes"),
         }
     }
 
@@ -257,6 +301,55 @@ impl ConstraintType {
             ConstraintType::IslandType => String::from("type"),
             ConstraintType::IslandResMore => String::from("ressource_plus"),
             ConstraintType::IslandResLess => String::from("ressource_minus"),
+            ConstraintType::PlayerAverageTownPoints => String::from("average_town_points"),
+            ConstraintType::AlliancePointsPerMember => String::from("points_per_member"),
+            ConstraintType::TownOcean => String::from("ocean"),
+            ConstraintType::TownPlayerTownsOnIsland => String::from("player_towns_on_island"),
+            ConstraintType::TownAllianceOceanShare => String::from("alliance_ocean_share"),
+            ConstraintType::PlayerPointsChange => String::from("points_change"),
+            ConstraintType::PlayerAllianceChanged => String::from("alliance_changed"),
+            ConstraintType::PlayerHoursInactive => String::from("hours_inactive"),
+            ConstraintType::TownOwnerChanged => String::from("owner_changed"),
+            ConstraintType::TownFounded => String::from("founded"),
+            ConstraintType::TownBecameGhost => String::from("became_ghost"),
+        }
+    }
+
+    /// Where the attribute is found in the town queries. The raw attributes of players, alliances
+    /// and islands are copied into the pre-joined town table, which is called `towns` there.
+    pub fn column(self) -> String {
+        match self {
+            ConstraintType::PlayerID => String::from("towns.player_id"),
+            ConstraintType::PlayerName
+            | ConstraintType::PlayerPoints
+            | ConstraintType::PlayerRank
+            | ConstraintType::PlayerTowns => format!("towns.player_{}", self.property()),
+            ConstraintType::AllianceName
+            | ConstraintType::AlliancePoints
+            | ConstraintType::AllianceTowns
+            | ConstraintType::AllianceMembers
+            | ConstraintType::AllianceRank => format!("towns.alliance_{}", self.property()),
+            ConstraintType::IslandX => String::from("towns.island_x"),
+            ConstraintType::IslandY => String::from("towns.island_y"),
+            ConstraintType::IslandID => String::from("towns.island_id"),
+            ConstraintType::IslandType
+            | ConstraintType::IslandTowns
+            | ConstraintType::IslandResMore
+            | ConstraintType::IslandResLess => format!("towns.island_{}", self.property()),
+            ConstraintType::TownID
+            | ConstraintType::TownName
+            | ConstraintType::TownPoints
+            | ConstraintType::PlayerAverageTownPoints
+            | ConstraintType::PlayerPointsChange
+            | ConstraintType::PlayerAllianceChanged
+            | ConstraintType::PlayerHoursInactive
+            | ConstraintType::AlliancePointsPerMember
+            | ConstraintType::TownOcean
+            | ConstraintType::TownPlayerTownsOnIsland
+            | ConstraintType::TownAllianceOceanShare
+            | ConstraintType::TownOwnerChanged
+            | ConstraintType::TownFounded
+            | ConstraintType::TownBecameGhost => format!("{}.{}", self.table(), self.property()),
         }
     }
 
@@ -272,12 +365,23 @@ impl ConstraintType {
             | ConstraintType::PlayerPoints
             | ConstraintType::PlayerRank
             | ConstraintType::PlayerTowns
+            | ConstraintType::PlayerAverageTownPoints
+            | ConstraintType::PlayerPointsChange
+            | ConstraintType::PlayerAllianceChanged
+            | ConstraintType::PlayerHoursInactive
             | ConstraintType::AlliancePoints
             | ConstraintType::AllianceTowns
             | ConstraintType::AllianceMembers
             | ConstraintType::AllianceRank
+            | ConstraintType::AlliancePointsPerMember
             | ConstraintType::TownID
             | ConstraintType::TownPoints
+            | ConstraintType::TownOcean
+            | ConstraintType::TownPlayerTownsOnIsland
+            | ConstraintType::TownAllianceOceanShare
+            | ConstraintType::TownOwnerChanged
+            | ConstraintType::TownFounded
+            | ConstraintType::TownBecameGhost
             | ConstraintType::IslandID
             | ConstraintType::IslandX
             | ConstraintType::IslandY
@@ -293,8 +397,16 @@ pub enum Comparator {
     Equal,
     GreaterThan,
     NotEqual,
+    /// the value is a list of values separated by commas
+    InList,
     InSelection,
     NotInSelection,
+    Top,
+    Bottom,
+    TopPerPlayer,
+    BottomPerPlayer,
+    TopPerAlliance,
+    BottomPerAlliance,
 }
 
 impl Comparator {
@@ -304,10 +416,60 @@ impl Comparator {
             Comparator::Equal => "=".to_string(),
             Comparator::GreaterThan => ">=".to_string(),
             Comparator::NotEqual => "<>".to_string(),
-            Comparator::InSelection => "IN".to_string(),
+            Comparator::InList
+            | Comparator::InSelection
+            | Comparator::Top
+            | Comparator::Bottom
+            | Comparator::TopPerPlayer
+            | Comparator::BottomPerPlayer
+            | Comparator::TopPerAlliance
+            | Comparator::BottomPerAlliance => "IN".to_string(),
             Comparator::NotInSelection => "NOT IN".to_string(),
         }
     }
+
+    /// Rank comparators keep the first N towns when ordered by the constraint type,
+    /// instead of comparing the constraint type against a threshold.
+    pub fn is_rank(self) -> bool {
+        match self {
+            Comparator::LessThan
+            | Comparator::Equal
+            | Comparator::GreaterThan
+            | Comparator::NotEqual
+            | Comparator::InList
+            | Comparator::InSelection
+            | Comparator::NotInSelection => false,
+            Comparator::Top
+            | Comparator::Bottom
+            | Comparator::TopPerPlayer
+            | Comparator::BottomPerPlayer
+            | Comparator::TopPerAlliance
+            | Comparator::BottomPerAlliance => true,
+        }
+    }
+
+    /// The sort order used to rank the towns. Only meaningful for rank comparators.
+    pub fn rank_order_sql(self) -> String {
+        match self {
+            Comparator::Bottom | Comparator::BottomPerPlayer | Comparator::BottomPerAlliance => {
+                "ASC".to_string()
+            }
+            _ => "DESC".to_string(),
+        }
+    }
+
+    /// The partition within which the towns are ranked. Only meaningful for rank comparators.
+    pub fn rank_partition_sql(self) -> String {
+        match self {
+            Comparator::TopPerPlayer | Comparator::BottomPerPlayer => {
+                "PARTITION BY towns.player_id".to_string()
+            }
+            Comparator::TopPerAlliance | Comparator::BottomPerAlliance => {
+                "PARTITION BY towns.alliance_id".to_string()
+            }
+            _ => String::new(),
+        }
+    }
 }
 
 impl ToString for Comparator {
@@ -317,8 +479,15 @@ impl ToString for Comparator {
             Comparator::Equal => "=".to_string(),
             Comparator::GreaterThan => ">=".to_string(),
             Comparator::NotEqual => "!=".to_string(),
+            Comparator::InList => t!("selection.comparator.in_list"),
             Comparator::InSelection => t!("selection.comparator.in"),
             Comparator::NotInSelection => t!("selection.comparator.not_in"),
+            Comparator::Top => t!("selection.comparator.top"),
+            Comparator::Bottom => t!("selection.comparator.bottom"),
+            Comparator::TopPerPlayer => t!("selection.comparator.top_per_player"),
+            Comparator::BottomPerPlayer => t!("selection.comparator.bottom_per_player"),
+            Comparator::TopPerAlliance => t!("selection.comparator.top_per_alliance"),
+            Comparator::BottomPerAlliance => t!("selection.comparator.bottom_per_alliance"),
         }
     }
 }
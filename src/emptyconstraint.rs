@@ -1,8 +1,4 @@
-use crate::{
-    constraint::{Comparator, Constraint, ConstraintType},
-    emptyselection::EmptyTownSelection,
-    model::database::Database,
-};
+use crate::constraint::{Comparator, Constraint, ConstraintType};
 use std::{
     fmt,
     hash::{Hash, Hasher},
@@ -37,64 +33,46 @@ impl EmptyConstraint {
         }
     }
 
+    /// The entries of the value of an `InList` constraint
+    pub fn list_values(&self) -> impl Iterator<Item = &str> {
+        self.value
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    }
+
     pub fn referenced_selection(&self) -> Option<String> {
         match self.comparator {
             Comparator::LessThan
             | Comparator::Equal
             | Comparator::GreaterThan
-            | Comparator::NotEqual => None,
+            | Comparator::NotEqual
+            | Comparator::InList => None,
             Comparator::InSelection | Comparator::NotInSelection => Some(self.value.clone()),
+            Comparator::Top
+            | Comparator::Bottom
+            | Comparator::TopPerPlayer
+            | Comparator::BottomPerPlayer
+            | Comparator::TopPerAlliance
+            | Comparator::BottomPerAlliance => self.rank_limit().1,
         }
     }
 
-    pub fn get_sql_value(&self, db: &Database, all_selections: &[EmptyTownSelection]) -> String {
-        match self.comparator {
-            Comparator::LessThan
-            | Comparator::Equal
-            | Comparator::GreaterThan
-            | Comparator::NotEqual => self.value.clone(),
-            Comparator::InSelection | Comparator::NotInSelection => {
-                let definitely_true = format!(
-                    "SELECT {0}.{1} FROM {0}",
-                    self.constraint_type.table(),
-                    self.constraint_type.property()
-                );
-                let definitely_false = String::new();
-
-                if self.value.is_empty() {
-                    return definitely_true;
-                }
-
-                let target_selection = all_selections
-                    .iter()
-                    .find(|&selection| selection.name == self.value);
-                if let Some(selection) = target_selection {
-                    // user has typed in a valid name
-                    let selection_clause = format!(
-                        "{}.{}",
-                        self.constraint_type.table(),
-                        self.constraint_type.property()
-                    );
-
-                    // TODO error handling
-                    db.selection_to_sql(&selection_clause, selection, all_selections)
-                        .unwrap()
-                } else {
-                    // The user typed in a selection name that does not exist. If the user wants towns that are IN
-                    // this imaginary selection, they'll get none. If they want all town that are NOT IN this imaginary
-                    // selection they'll get all possible ones (an empty selection does not restrict the search in any way)
-                    if self.comparator == Comparator::NotInSelection {
-                        definitely_true
-                    } else if self.comparator == Comparator::InSelection {
-                        definitely_false
-                    } else {
-                        unreachable!(
-                            "The comparator type somehow changed between the match and the if case"
-                        )
-                    }
-                }
-            }
-        }
+    /// The value of a rank constraint is the number of towns to keep, optionally followed by the
+    /// name of the selection within which the towns are ranked, e.g. "20" or "20 Enemy Alliance".
+    /// A value that does not start with a number keeps no towns.
+    pub fn rank_limit(&self) -> (usize, Option<String>) {
+        let mut split = self.value.trim().splitn(2, ' ');
+        let count = split
+            .next()
+            .and_then(|text| text.parse::<usize>().ok())
+            .unwrap_or(0);
+        let scope = split
+            .next()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(String::from);
+        (count, scope)
     }
 }
 
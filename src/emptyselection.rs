@@ -1,18 +1,23 @@
-use anyhow::Context;
 use rand::distributions::{Alphanumeric, DistString};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, HashSet};
 use std::default::Default;
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::emptyconstraint::EmptyConstraint;
-use crate::selection::{AndOr, SelectionState, TownSelection};
+use crate::gradient::{Gradient, GradientValues};
+use crate::heatmap::{Heatmap, HeatmapSettings};
+use crate::label::LabelStyle;
+use crate::selection::{AndOr, Grouping, SelectionState, TownSelection};
+use crate::town::SelectionStats;
+use crate::view::groups::GroupSort;
+use crate::view::table::TownSort;
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone, Serialize, Deserialize, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct EmptyTownSelection {
     #[serde(default = "String::new")]
     pub name: String,
@@ -25,6 +30,18 @@ pub struct EmptyTownSelection {
 
     #[serde(default)]
     pub color: egui::Color32,
+
+    #[serde(default)]
+    pub grouping: Grouping,
+
+    #[serde(default)]
+    pub gradient: Option<Gradient>,
+
+    #[serde(default)]
+    pub label: LabelStyle,
+
+    #[serde(default)]
+    pub heatmap: Option<HeatmapSettings>,
 }
 
 impl Default for EmptyTownSelection {
@@ -34,6 +51,10 @@ impl Default for EmptyTownSelection {
             constraints: vec![EmptyConstraint::default()],
             constraint_join_mode: AndOr::default(),
             color: egui::Color32::GREEN,
+            grouping: Grouping::default(),
+            gradient: None,
+            label: LabelStyle::default(),
+            heatmap: None,
         }
     }
 }
@@ -90,7 +111,22 @@ impl EmptyTownSelection {
             constraints: self.constraints.iter().map(EmptyConstraint::fill).collect(),
             constraint_join_mode: self.constraint_join_mode,
             color: self.color,
+            grouping: self.grouping,
+            gradient: self.gradient,
+            label: self.label,
+            gradient_values: Arc::new(GradientValues::default()),
+            heatmap: self.heatmap,
+            heatmap_values: Arc::new(Heatmap::default()),
             towns: Arc::new(Vec::new()),
+            groups: Arc::new(Vec::new()),
+            stats: Arc::new(SelectionStats::default()),
+            group_sort: GroupSort::default(),
+            show_table: false,
+            bbcode_origin: String::new(),
+            town_sort: TownSort::default(),
+            table_error: None,
+            bbcode_error: None,
+            error: None,
         }
     }
 
@@ -182,40 +218,4 @@ impl EmptyTownSelection {
 
         cycle_detected
     }
-
-    pub fn try_from_str(text: &str) -> anyhow::Result<Vec<Self>> {
-        // Attempt to parse text as a vector of selections, and it that doesn't work, parse it as a single selection.
-        let res_parse_as_vec = serde_yaml::from_str(text);
-        let res_parse_as_single = serde_yaml::from_str(text);
-
-        match (res_parse_as_vec, res_parse_as_single) {
-            (Ok(vec), _) => Ok(vec),
-            (Err(_err), Ok(selection)) => Ok(vec![selection]),
-            (Err(err_vec), Err(err_single)) => {
-                eprintln!("Could not parse text ({text}) as TownSelection (Error: {err_single:?}) or Vec<TownSelection> (Error: {err_vec:?}).");
-                Err(
-                    anyhow::Error::new(err_vec)
-                    .context(err_single)
-                    .context("Could not parse text ({text}) as TownSelection (Error: {single_err:?}) or Vec<TownSelection> (Error: {vec_err:?}).")
-                )
-            }
-        }
-    }
-
-    pub fn try_from_path(files: &[PathBuf]) -> Vec<anyhow::Result<Vec<Self>>> {
-        let mut re = Vec::with_capacity(files.len());
-        for file in files {
-            let content = std::fs::read_to_string(file);
-            re.push(match content {
-                Ok(content) => {
-                    Self::try_from_str(&content).with_context(|| {format!("Failed to convert content of file {file:?} to an instance of TownSelection. Content of file is: {content}")})
-                }
-                Err(err) => {
-                        Err(err)
-                            .with_context(|| format!("Failed to read content of file {file:?}"))
-                }
-            });
-        }
-        re
-    }
 }
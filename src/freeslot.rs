@@ -0,0 +1,82 @@
+use rusqlite::Row;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::spatial::Positioned;
+use crate::town::parse_island_coordinates;
+
+/// A slot on an island that no town occupies. New towns can be founded here.
+#[derive(Debug, Clone)]
+pub struct FreeSlot {
+    pub island_x: u16,
+    pub island_y: u16,
+    pub slot_number: u8,
+    pub x: f32,
+    pub y: f32,
+    pub ocean: u8,
+}
+
+impl FreeSlot {
+    pub fn from(row: &Row) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            island_x: row.get(0)?,
+            island_y: row.get(1)?,
+            slot_number: row.get(2)?,
+            x: row.get::<usize, f32>(0)? + row.get::<usize, f32>(3)? / 125.0,
+            y: row.get::<usize, f32>(1)? + row.get::<usize, f32>(4)? / 125.0,
+            ocean: row.get(5)?,
+        })
+    }
+}
+
+impl Positioned for FreeSlot {
+    fn position(&self) -> egui::Vec2 {
+        egui::vec2(self.x, self.y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+pub enum Resource {
+    Wood,
+    Stone,
+    Iron,
+}
+
+impl ToString for Resource {
+    fn to_string(&self) -> String {
+        match self {
+            Resource::Wood => t!("sidepanel.free_slots.wood"),
+            Resource::Stone => t!("sidepanel.free_slots.stone"),
+            Resource::Iron => t!("sidepanel.free_slots.iron"),
+        }
+    }
+}
+
+impl Resource {
+    /// The value used in the island data from the grepolis servers
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            Resource::Wood => "wood",
+            Resource::Stone => "stone",
+            Resource::Iron => "iron",
+        }
+    }
+}
+
+/// Restricts which free slots are shown. Every field that is None matches all slots.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FreeSlotFilter {
+    pub ocean: Option<u8>,
+    /// island coordinates as written in game, e.g. "475|512"
+    pub island: String,
+    pub ressource_plus: Option<Resource>,
+    pub ressource_minus: Option<Resource>,
+}
+
+impl FreeSlotFilter {
+    /// The island coordinates, if the user entered valid ones
+    pub fn island(&self) -> Option<(u16, u16)> {
+        parse_island_coordinates(&self.island)
+    }
+}
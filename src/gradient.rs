@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+use crate::constraint::ConstraintType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, EnumIter)]
+pub enum Scale {
+    Linear,
+    Logarithmic,
+}
+
+impl ToString for Scale {
+    fn to_string(&self) -> String {
+        match self {
+            Scale::Linear => t!("selection.gradient.linear"),
+            Scale::Logarithmic => t!("selection.gradient.logarithmic"),
+        }
+    }
+}
+
+/// Colour the towns of a selection by a numeric attribute instead of a single colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gradient {
+    pub attribute: ConstraintType,
+    /// colour of the town with the smallest value
+    pub low: egui::Color32,
+    /// colour of the town with the largest value
+    pub high: egui::Color32,
+    pub scale: Scale,
+}
+
+impl Default for Gradient {
+    fn default() -> Self {
+        Self {
+            attribute: ConstraintType::TownPoints,
+            low: egui::Color32::from_rgb(255, 255, 0),
+            high: egui::Color32::from_rgb(255, 0, 0),
+            scale: Scale::Linear,
+        }
+    }
+}
+
+impl Gradient {
+    /// Where `value` lies between `min` and `max`, from 0 to 1
+    fn position(self, value: f64, min: f64, max: f64) -> f32 {
+        if max <= min {
+            return 1.0;
+        }
+        // shift everything, so the logarithm also works for negative values like points changes
+        let position = match self.scale {
+            Scale::Linear => (value - min) / (max - min),
+            Scale::Logarithmic => (value - min).ln_1p() / (max - min).ln_1p(),
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let position = position.clamp(0.0, 1.0) as f32;
+        position
+    }
+
+    pub fn color_at(self, position: f32) -> egui::Color32 {
+        let lerp = |low: u8, high: u8| {
+            let value = f32::from(low) + (f32::from(high) - f32::from(low)) * position;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let value = value.round().clamp(0.0, 255.0) as u8;
+            value
+        };
+        egui::Color32::from_rgba_premultiplied(
+            lerp(self.low.r(), self.high.r()),
+            lerp(self.low.g(), self.high.g()),
+            lerp(self.low.b(), self.high.b()),
+            lerp(self.low.a(), self.high.a()),
+        )
+    }
+
+    pub fn color(self, value: f64, min: f64, max: f64) -> egui::Color32 {
+        self.color_at(self.position(value, min, max))
+    }
+}
+
+/// The value of the gradient attribute for each town of a selection
+#[derive(Debug, Clone, Default)]
+pub struct GradientValues {
+    pub values: HashMap<i32, f64>,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl GradientValues {
+    pub fn new(values: HashMap<i32, f64>) -> Self {
+        let min = values.values().copied().reduce(f64::min).unwrap_or(0.0);
+        let max = values.values().copied().reduce(f64::max).unwrap_or(0.0);
+        Self { values, min, max }
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+/// The world is 1000 units wide and high
+const WORLD_SIZE: f32 = 1000.0;
+/// The grid gets this many cells along the radius, but they are never smaller than
+/// `MIN_CELL_SIZE`, so the grid stays small
+const CELLS_PER_RADIUS: f32 = 4.0;
+const MIN_CELL_SIZE: f32 = 2.0;
+
+/// What a town contributes to the density around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, EnumIter)]
+pub enum Weighting {
+    #[default]
+    Count,
+    TownPoints,
+    PlayerPoints,
+}
+
+impl ToString for Weighting {
+    fn to_string(&self) -> String {
+        match self {
+            Weighting::Count => t!("selection.heatmap.count"),
+            Weighting::TownPoints => t!("selection.heatmap.town_points"),
+            Weighting::PlayerPoints => t!("selection.heatmap.player_points"),
+        }
+    }
+}
+
+/// Show where the towns of a selection are concentrated, below its towns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeatmapSettings {
+    /// how far a town contributes to the density, in world units
+    pub radius: u8,
+    pub weighting: Weighting,
+}
+
+impl Default for HeatmapSettings {
+    fn default() -> Self {
+        Self {
+            radius: 10,
+            weighting: Weighting::default(),
+        }
+    }
+}
+
+/// The density of a selection on a grid over the whole world
+#[derive(Debug, Clone, Default)]
+pub struct Heatmap {
+    /// number of cells along each side
+    pub side: usize,
+    /// row by row, from the top left
+    pub cells: Vec<f32>,
+    pub max: f32,
+}
+
+impl Heatmap {
+    /// Spread the weight of every point over the cells within `radius` of it. The weight falls
+    /// off smoothly towards the edge of the radius.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(points: impl Iterator<Item = (egui::Vec2, f64)>, radius: f32) -> Self {
+        let radius = radius.max(1.0);
+        let cell_size = (radius / CELLS_PER_RADIUS).max(MIN_CELL_SIZE);
+        let side = (WORLD_SIZE / cell_size).ceil() as usize;
+        let reach = (radius / cell_size).ceil() as isize;
+        let mut cells = vec![0.0_f32; side * side];
+
+        for (position, weight) in points {
+            let column = (position.x / cell_size).floor() as isize;
+            let row = (position.y / cell_size).floor() as isize;
+            for y in (row - reach).max(0)..=(row + reach).min(side as isize - 1) {
+                for x in (column - reach).max(0)..=(column + reach).min(side as isize - 1) {
+                    let center = egui::vec2(x as f32 + 0.5, y as f32 + 0.5) * cell_size;
+                    let distance = (center - position).length_sq() / (radius * radius);
+                    if distance < 1.0 {
+                        cells[y as usize * side + x as usize] +=
+                            weight as f32 * (1.0 - distance).powi(2);
+                    }
+                }
+            }
+        }
+
+        let max = cells.iter().copied().fold(0.0, f32::max);
+        Self { side, cells, max }
+    }
+
+    /// The colour of a cell: the colour of the selection, transparent where there are no towns.
+    /// The square root makes sparse areas visible next to the densest one.
+    fn cell_color(&self, value: f32, color: egui::Color32) -> egui::Color32 {
+        let [r, g, b, a] = color.to_srgba_unmultiplied();
+        let strength = if self.max > 0.0 {
+            (value / self.max).sqrt()
+        } else {
+            0.0
+        };
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let alpha = (strength * f32::from(a)).round() as u8;
+        egui::Color32::from_rgba_unmultiplied(r, g, b, alpha)
+    }
+
+    /// The density in the colour of the selection, one pixel per cell
+    pub fn image(&self, color: egui::Color32) -> egui::ColorImage {
+        let pixels = self
+            .cells
+            .iter()
+            .map(|value| self.cell_color(*value, color))
+            .collect();
+        egui::ColorImage {
+            size: [self.side, self.side],
+            pixels,
+        }
+    }
+
+    /// Runs of cells with the same colour in a row, as a rectangle in world coordinates. Fewer
+    /// shapes than one per cell when exporting. Like the texture on the map, the cells are
+    /// stretched over the whole world.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn regions(&self, color: egui::Color32) -> Vec<(egui::Rect, egui::Color32)> {
+        let mut regions = Vec::new();
+        if self.side == 0 {
+            return regions;
+        }
+        let cell_size = WORLD_SIZE / self.side as f32;
+        for (y, row) in self.cells.chunks(self.side).enumerate() {
+            let mut x = 0;
+            while x < self.side {
+                let fill = self.cell_color(row[x], color);
+                let start = x;
+                while x < self.side && self.cell_color(row[x], color) == fill {
+                    x += 1;
+                }
+                if fill.a() > 0 {
+                    regions.push((
+                        egui::Rect::from_min_max(
+                            egui::pos2(start as f32 * cell_size, y as f32 * cell_size),
+                            egui::pos2(x as f32 * cell_size, (y + 1) as f32 * cell_size),
+                        ),
+                        fill,
+                    ));
+                }
+            }
+        }
+        regions
+    }
+}
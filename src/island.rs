@@ -0,0 +1,91 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Islands are drawn with their outline once the map is zoomed in further than this, and as a
+/// simple dot otherwise
+pub const ISLAND_ZOOM: f32 = 4.0;
+
+/// How far the coast is from the outermost town slots, in world units
+const COAST_MARGIN: f32 = 0.35;
+
+/// An island of the `islands` table with the shape of its island type
+#[derive(Debug, Clone)]
+pub struct Island {
+    pub x: f32,
+    pub y: f32,
+    /// Corners of the island relative to its position. Empty for island types without town
+    /// slots, we don't know their shape.
+    pub outline: Arc<Vec<egui::Vec2>>,
+}
+
+impl Island {
+    /// The middle of the island, which is not its position. Islands are positioned by their top
+    /// left corner.
+    pub fn center(&self) -> egui::Vec2 {
+        if self.outline.is_empty() {
+            return egui::vec2(self.x, self.y);
+        }
+        egui::vec2(self.x, self.y) + centroid(&self.outline)
+    }
+}
+
+/// The average of some points, zero if there are none
+fn centroid(points: &[egui::Vec2]) -> egui::Vec2 {
+    #[allow(clippy::cast_precision_loss)]
+    let count = points.len().max(1) as f32;
+    points
+        .iter()
+        .fold(egui::Vec2::ZERO, |sum, point| sum + *point)
+        / count
+}
+
+/// The convex hull of a set of points, by Andrew's monotone chain algorithm
+fn convex_hull(mut points: Vec<egui::Vec2>) -> Vec<egui::Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: egui::Vec2, a: egui::Vec2, b: egui::Vec2| {
+        (a.x - o.x) * (b.y - o.y) - (a.y - o.y) * (b.x - o.x)
+    };
+    let mut hull: Vec<egui::Vec2> = Vec::with_capacity(2 * points.len());
+    // lower hull, then upper hull
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // the last point of each pass is the first point of the next one
+        hull.pop();
+    }
+    hull
+}
+
+/// The outline of every island type, made from the offsets of its town slots. Towns lie on the
+/// coast, so the hull of the slots is pushed out a bit to get the coast line.
+pub fn outlines(offsets: &[(u16, egui::Vec2)]) -> BTreeMap<u16, Arc<Vec<egui::Vec2>>> {
+    let mut slots_by_type: BTreeMap<u16, Vec<egui::Vec2>> = BTreeMap::new();
+    for (island_type, offset) in offsets {
+        slots_by_type.entry(*island_type).or_default().push(*offset);
+    }
+
+    slots_by_type
+        .into_iter()
+        .map(|(island_type, slots)| {
+            let hull = convex_hull(slots);
+            let center = centroid(&hull);
+            let outline = hull
+                .into_iter()
+                .map(|corner| corner + (corner - center).normalized() * COAST_MARGIN)
+                .collect();
+            (island_type, Arc::new(outline))
+        })
+        .collect()
+}
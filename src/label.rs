@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::town::Town;
+
+/// Labels are only drawn once the map is zoomed in further than this
+pub const LABEL_ZOOM: f32 = 12.0;
+
+/// What is written next to the towns of a layer or selection when the map is zoomed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, EnumIter)]
+pub enum LabelContent {
+    #[default]
+    Nothing,
+    TownName,
+    PlayerName,
+    AllianceName,
+}
+
+impl ToString for LabelContent {
+    fn to_string(&self) -> String {
+        match self {
+            LabelContent::Nothing => t!("map.label.nothing"),
+            LabelContent::TownName => t!("map.label.town_name"),
+            LabelContent::PlayerName => t!("map.label.player_name"),
+            LabelContent::AllianceName => t!("map.label.alliance_name"),
+        }
+    }
+}
+
+impl LabelContent {
+    /// None if the town has no label of this kind, e.g. ghost towns don't have a player
+    pub fn text(self, town: &Town) -> Option<&str> {
+        match self {
+            LabelContent::Nothing => None,
+            LabelContent::TownName => Some(town.name.as_str()),
+            LabelContent::PlayerName => town.player_name.as_deref(),
+            LabelContent::AllianceName => town.alliance_name.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelStyle {
+    pub content: LabelContent,
+    /// font size in points
+    pub size: u8,
+}
+
+impl Default for LabelStyle {
+    fn default() -> Self {
+        Self {
+            content: LabelContent::default(),
+            size: 12,
+        }
+    }
+}
+
+impl LabelStyle {
+    pub fn is_enabled(self) -> bool {
+        self.content != LabelContent::Nothing
+    }
+
+    /// A combobox for the content and, if there is a label, the font size
+    pub fn ui(&mut self, ui: &mut egui::Ui, id_source: &str) {
+        egui::ComboBox::from_id_source(id_source)
+            .selected_text(self.content.to_string())
+            .show_ui(ui, |ui| {
+                for content in LabelContent::iter() {
+                    let text = content.to_string();
+                    ui.selectable_value(&mut self.content, content, text);
+                }
+            });
+        if self.is_enabled() {
+            ui.add(
+                egui::DragValue::new(&mut self.size)
+                    .clamp_range(6..=32)
+                    .speed(0.2)
+                    .suffix(" pt"),
+            );
+        }
+    }
+}
+
+/// A label that found a place next to its town
+pub struct PlacedLabel<'a> {
+    pub rect: egui::Rect,
+    pub text: &'a str,
+    pub size: f32,
+    pub color: egui::Color32,
+}
+
+/// Place the labels next to their towns, but skip every label that would overlap one that was
+/// already placed. Every town gets one label at most. `position` is where a town is drawn,
+/// `offset` the distance of the label from it and `measure` the size of a text in a font size.
+pub fn place_labels<'a>(
+    labels: &[(&'a Town, LabelStyle, egui::Color32)],
+    offset: f32,
+    position: impl Fn(&Town) -> egui::Pos2,
+    measure: impl Fn(&str, f32) -> egui::Vec2,
+) -> Vec<PlacedLabel<'a>> {
+    let mut placed: Vec<PlacedLabel> = Vec::new();
+    let mut labelled = HashSet::new();
+    for (town, style, color) in labels {
+        let Some(text) = style.content.text(town) else {
+            continue;
+        };
+        if !labelled.insert(town.id) {
+            continue;
+        }
+        let size = f32::from(style.size);
+        let rect = egui::Align2::LEFT_CENTER.anchor_rect(egui::Rect::from_min_size(
+            position(town) + egui::vec2(offset, 0.0),
+            measure(text, size),
+        ));
+        if placed.iter().any(|other| other.rect.intersects(rect)) {
+            continue;
+        }
+        placed.push(PlacedLabel {
+            rect,
+            text,
+            size,
+            color: *color,
+        });
+    }
+    placed
+}
@@ -7,16 +7,27 @@
 extern crate rust_i18n;
 i18n!("locales", fallback = "en");
 
+mod bbcode;
 mod constraint;
 mod emptyconstraint;
 mod emptyselection;
+mod freeslot;
+mod gradient;
+mod heatmap;
+mod island;
+mod label;
+mod mapimage;
 mod message;
 mod model;
 mod presenter;
 mod selection;
+mod spatial;
 mod storage;
 mod telemetry;
+mod territory;
+mod tms;
 mod town;
+mod travel;
 mod view;
 
 use std::{sync::mpsc, thread};
@@ -0,0 +1,458 @@
+use std::fmt::Write;
+
+use ab_glyph::{Font, FontRef, PxScale, ScaleFont};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumIter;
+
+/// Length of the dashes and gaps of dashed lines, in pixels
+const DASH: f32 = 7.0;
+/// The progress of drawing a PNG is reported after this many shapes
+const PROGRESS_STEP: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "PNG",
+            ImageFormat::Svg => "SVG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+        }
+    }
+}
+
+/// Something to draw on an exported map, in pixels of the image
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Circle {
+        center: egui::Pos2,
+        radius: f32,
+        fill: egui::Color32,
+    },
+    Ring {
+        center: egui::Pos2,
+        radius: f32,
+        stroke: egui::Stroke,
+    },
+    Line {
+        points: [egui::Pos2; 2],
+        stroke: egui::Stroke,
+        dashed: bool,
+    },
+    Rect {
+        rect: egui::Rect,
+        fill: egui::Color32,
+    },
+    Polygon {
+        points: Vec<egui::Pos2>,
+        fill: egui::Color32,
+    },
+    Text {
+        pos: egui::Pos2,
+        anchor: egui::Align2,
+        text: String,
+        size: f32,
+        color: egui::Color32,
+    },
+}
+
+/// Everything that is needed to draw an image of the map. It does not know about the window it
+/// was made from, so the size of the image is independent of the size of the window.
+#[derive(Debug, Clone)]
+pub struct Scene {
+    pub width: u32,
+    pub height: u32,
+    pub background: egui::Color32,
+    pub shapes: Vec<Shape>,
+}
+
+fn paint(color: egui::Color32) -> tiny_skia::Paint<'static> {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let mut paint = tiny_skia::Paint::default();
+    paint.set_color_rgba8(r, g, b, a);
+    paint.anti_alias = true;
+    paint
+}
+
+/// Straight lines from one point to the next, None if there are less than two points
+fn path_through(points: &[egui::Pos2], closed: bool) -> Option<tiny_skia::Path> {
+    let mut builder = tiny_skia::PathBuilder::new();
+    let (first, rest) = points.split_first()?;
+    builder.move_to(first.x, first.y);
+    for point in rest {
+        builder.line_to(point.x, point.y);
+    }
+    if closed {
+        builder.close();
+    }
+    builder.finish()
+}
+
+/// The color and opacity of a fill or stroke as SVG attributes
+fn svg_paint(attribute: &str, color: egui::Color32) -> String {
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    if a == 255 {
+        format!(r#"{attribute}="rgb({r},{g},{b})""#)
+    } else {
+        let opacity = f32::from(a) / 255.0;
+        format!(r#"{attribute}="rgb({r},{g},{b})" {attribute}-opacity="{opacity:.3}""#)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The glyphs of a line of text next to each other, and the width of the line
+fn layout(font: &FontRef, text: &str, size: f32) -> (Vec<ab_glyph::Glyph>, f32) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(scaled.scale(), ab_glyph::point(caret, 0.0)));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    (glyphs, caret)
+}
+
+/// Measures text the way it is drawn into a PNG, so labels can be placed before drawing
+pub struct TextMeasure<'a> {
+    font: FontRef<'a>,
+}
+
+impl<'a> TextMeasure<'a> {
+    pub fn new(font: &'a [u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            font: FontRef::try_from_slice(font).context("Failed to read the font")?,
+        })
+    }
+
+    pub fn size(&self, text: &str, size: f32) -> egui::Vec2 {
+        let scaled = self.font.as_scaled(PxScale::from(size));
+        let (_, width) = layout(&self.font, text, size);
+        egui::vec2(width, scaled.ascent() - scaled.descent())
+    }
+}
+
+/// Write a line of text into the image. tiny-skia can't draw text, so we lay out and rasterize
+/// the glyphs ourselves and blend them into the pixels.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn draw_text(
+    pixmap: &mut tiny_skia::Pixmap,
+    font: &FontRef,
+    pos: egui::Pos2,
+    anchor: egui::Align2,
+    text: &str,
+    size: f32,
+    color: egui::Color32,
+) {
+    let scaled = font.as_scaled(PxScale::from(size));
+    let (glyphs, caret) = layout(font, text, size);
+    let height = scaled.ascent() - scaled.descent();
+    let left = match anchor.x() {
+        egui::Align::Min => pos.x,
+        egui::Align::Center => pos.x - caret / 2.0,
+        egui::Align::Max => pos.x - caret,
+    };
+    let top = match anchor.y() {
+        egui::Align::Min => pos.y,
+        egui::Align::Center => pos.y - height / 2.0,
+        egui::Align::Max => pos.y - height,
+    };
+    let baseline = top + scaled.ascent();
+
+    let [r, g, b, a] = color.to_srgba_unmultiplied();
+    let image_width = pixmap.width() as f32;
+    let image_height = pixmap.height() as f32;
+    let pixels = pixmap.pixels_mut();
+    for glyph in glyphs {
+        let Some(outline) = font.outline_glyph(glyph) else {
+            // e.g. spaces
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        outline.draw(|glyph_x, glyph_y, coverage| {
+            let column = (left + bounds.min.x).round() + glyph_x as f32;
+            let row = (baseline + bounds.min.y).round() + glyph_y as f32;
+            if column < 0.0 || row < 0.0 || column >= image_width || row >= image_height {
+                return;
+            }
+            let index = row as usize * image_width as usize + column as usize;
+            let alpha = f32::from(a) / 255.0 * coverage;
+            let old = pixels[index];
+            // the pixels are premultiplied, so the text color has to be multiplied as well
+            let blend = |source: u8, destination: u8| {
+                (f32::from(source) * alpha + f32::from(destination) * (1.0 - alpha)).round() as u8
+            };
+            if let Some(new) = tiny_skia::PremultipliedColorU8::from_rgba(
+                blend(r, old.red()),
+                blend(g, old.green()),
+                blend(b, old.blue()),
+                blend(255, old.alpha()),
+            ) {
+                pixels[index] = new;
+            }
+        });
+    }
+}
+
+impl Scene {
+    /// `progress` is called with the share of the shapes that is drawn, from 0 to 1
+    pub fn render(
+        &self,
+        format: ImageFormat,
+        font: &[u8],
+        progress: impl FnMut(f32),
+    ) -> anyhow::Result<Vec<u8>> {
+        match format {
+            ImageFormat::Png => self.to_png(font, progress),
+            ImageFormat::Svg => Ok(self.to_svg().into_bytes()),
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn to_png(&self, font: &[u8], mut progress: impl FnMut(f32)) -> anyhow::Result<Vec<u8>> {
+        let mut pixmap = tiny_skia::Pixmap::new(self.width, self.height).with_context(|| {
+            format!(
+                "Can't create an image of {}x{} pixels",
+                self.width, self.height
+            )
+        })?;
+        let [r, g, b, a] = self.background.to_srgba_unmultiplied();
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, a));
+        let font = FontRef::try_from_slice(font).context("Failed to read the font")?;
+        let identity = tiny_skia::Transform::identity();
+
+        for (index, shape) in self.shapes.iter().enumerate() {
+            if index % PROGRESS_STEP == 0 {
+                progress(index as f32 / self.shapes.len() as f32);
+            }
+            match shape {
+                Shape::Circle {
+                    center,
+                    radius,
+                    fill,
+                } => {
+                    if let Some(path) =
+                        tiny_skia::PathBuilder::from_circle(center.x, center.y, *radius)
+                    {
+                        pixmap.fill_path(
+                            &path,
+                            &paint(*fill),
+                            tiny_skia::FillRule::Winding,
+                            identity,
+                            None,
+                        );
+                    }
+                }
+                Shape::Ring {
+                    center,
+                    radius,
+                    stroke,
+                } => {
+                    if let Some(path) =
+                        tiny_skia::PathBuilder::from_circle(center.x, center.y, *radius)
+                    {
+                        let ring = tiny_skia::Stroke {
+                            width: stroke.width,
+                            ..tiny_skia::Stroke::default()
+                        };
+                        pixmap.stroke_path(&path, &paint(stroke.color), &ring, identity, None);
+                    }
+                }
+                Shape::Line {
+                    points,
+                    stroke,
+                    dashed,
+                } => {
+                    if let Some(path) = path_through(points, false) {
+                        let line = tiny_skia::Stroke {
+                            width: stroke.width,
+                            dash: if *dashed {
+                                tiny_skia::StrokeDash::new(vec![DASH, DASH], 0.0)
+                            } else {
+                                None
+                            },
+                            ..tiny_skia::Stroke::default()
+                        };
+                        pixmap.stroke_path(&path, &paint(stroke.color), &line, identity, None);
+                    }
+                }
+                Shape::Rect { rect, fill } => {
+                    if let Some(rect) = tiny_skia::Rect::from_ltrb(
+                        rect.left(),
+                        rect.top(),
+                        rect.right(),
+                        rect.bottom(),
+                    ) {
+                        pixmap.fill_rect(rect, &paint(*fill), identity, None);
+                    }
+                }
+                Shape::Polygon { points, fill } => {
+                    if let Some(path) = path_through(points, true) {
+                        pixmap.fill_path(
+                            &path,
+                            &paint(*fill),
+                            tiny_skia::FillRule::Winding,
+                            identity,
+                            None,
+                        );
+                    }
+                }
+                Shape::Text {
+                    pos,
+                    anchor,
+                    text,
+                    size,
+                    color,
+                } => draw_text(&mut pixmap, &font, *pos, *anchor, text, *size, *color),
+            }
+        }
+
+        progress(1.0);
+        pixmap
+            .encode_png()
+            .context("Failed to encode the map as PNG")
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn to_svg(&self) -> String {
+        let (width, height) = (self.width, self.height);
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+        );
+        svg.push('\n');
+        let _ = writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" {}/>"#,
+            svg_paint("fill", self.background)
+        );
+
+        for shape in &self.shapes {
+            let _ = match shape {
+                Shape::Circle {
+                    center,
+                    radius,
+                    fill,
+                } => writeln!(
+                    svg,
+                    r#"<circle cx="{:.2}" cy="{:.2}" r="{radius:.2}" {}/>"#,
+                    center.x,
+                    center.y,
+                    svg_paint("fill", *fill)
+                ),
+                Shape::Ring {
+                    center,
+                    radius,
+                    stroke,
+                } => writeln!(
+                    svg,
+                    r#"<circle cx="{:.2}" cy="{:.2}" r="{radius:.2}" fill="none" stroke-width="{}" {}/>"#,
+                    center.x,
+                    center.y,
+                    stroke.width,
+                    svg_paint("stroke", stroke.color)
+                ),
+                Shape::Line {
+                    points,
+                    stroke,
+                    dashed,
+                } => writeln!(
+                    svg,
+                    r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke-width="{}" {}{}/>"#,
+                    points[0].x,
+                    points[0].y,
+                    points[1].x,
+                    points[1].y,
+                    stroke.width,
+                    svg_paint("stroke", stroke.color),
+                    if *dashed {
+                        format!(r#" stroke-dasharray="{DASH} {DASH}""#)
+                    } else {
+                        String::new()
+                    }
+                ),
+                Shape::Rect { rect, fill } => writeln!(
+                    svg,
+                    r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" {}/>"#,
+                    rect.left(),
+                    rect.top(),
+                    rect.width(),
+                    rect.height(),
+                    svg_paint("fill", *fill)
+                ),
+                Shape::Polygon { points, fill } => writeln!(
+                    svg,
+                    r#"<polygon points="{}" {}/>"#,
+                    points
+                        .iter()
+                        .map(|point| format!("{:.2},{:.2}", point.x, point.y))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    svg_paint("fill", *fill)
+                ),
+                Shape::Text {
+                    pos,
+                    anchor,
+                    text,
+                    size,
+                    color,
+                } => {
+                    let text_anchor = match anchor.x() {
+                        egui::Align::Min => "start",
+                        egui::Align::Center => "middle",
+                        egui::Align::Max => "end",
+                    };
+                    let baseline = match anchor.y() {
+                        egui::Align::Min => "hanging",
+                        egui::Align::Center => "central",
+                        egui::Align::Max => "text-after-edge",
+                    };
+                    writeln!(
+                        svg,
+                        r#"<text x="{:.2}" y="{:.2}" font-family="sans-serif" font-size="{size}" text-anchor="{text_anchor}" dominant-baseline="{baseline}" {}>{}</text>"#,
+                        pos.x,
+                        pos.y,
+                        svg_paint("fill", *color),
+                        escape_xml(text)
+                    )
+                }
+            };
+        }
+
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
@@ -5,11 +5,18 @@ use std::{
     sync::Arc,
 };
 
+use crate::bbcode::ListEntry;
 use crate::emptyconstraint::EmptyConstraint;
 use crate::emptyselection::EmptyTownSelection;
+use crate::freeslot::{FreeSlot, FreeSlotFilter};
+use crate::gradient::GradientValues;
+use crate::heatmap::Heatmap;
+use crate::island::Island;
+use crate::mapimage::{ImageFormat, Scene};
 use crate::storage::SavedDB;
-use crate::town::Town;
-use crate::view::preferences::CacheSize;
+use crate::territory::Territory;
+use crate::town::{SelectionStats, Town, TownDetails, TownGroup};
+use crate::view::preferences::{CacheSize, InactivityWindow};
 
 /// This is a file for the messages passed between the view and the presenter.
 /// message passing communication allows them to be on separate threads. Also it's good code hygene
@@ -26,16 +33,33 @@ pub enum MessageToView {
     GotServer,
     AllTowns(Arc<Vec<Town>>),
     GhostTowns(Arc<Vec<Town>>),
+    ChangedTowns(Arc<Vec<Town>>),
+    FreeSlots(Arc<Vec<FreeSlot>>),
+    Islands(Arc<Vec<Island>>),
+    Territory(u8, Arc<Territory>),
+    TownDetails(i32, anyhow::Result<Option<Arc<TownDetails>>>),
+    GotBaseline,
+    GotActivity,
     TownListForSelection(EmptyTownSelection, Arc<Vec<Town>>),
+    GroupsForSelection(EmptyTownSelection, Arc<Vec<TownGroup>>),
+    StatsForSelection(EmptyTownSelection, Arc<SelectionStats>),
+    GradientValuesForSelection(EmptyTownSelection, Arc<GradientValues>),
+    HeatmapForSelection(EmptyTownSelection, Arc<Heatmap>),
+    SelectionFailed(EmptyTownSelection, anyhow::Error),
+    ImportedList(Option<EmptyTownSelection>, Vec<ListEntry>),
     ValueListForConstraint(EmptyConstraint, EmptyTownSelection, Arc<Vec<String>>),
     BackendCrashed(anyhow::Error),
     FoundSavedDatabases(BTreeMap<String, Vec<SavedDB>>),
     RemovedDatabases(Vec<SavedDB>),
+    /// share of the exported image that is drawn, from 0 to 1
+    ImageExportProgress(f32),
+    ImageExported(anyhow::Result<PathBuf>),
 
     VersionInfo(String, String),
 }
 
 impl fmt::Display for MessageToView {
+    #[allow(clippy::too_many_lines)] // one arm per message
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             MessageToView::GotServer => {
@@ -47,6 +71,43 @@ impl fmt::Display for MessageToView {
                 selection,
                 towns.len()
             ),
+            MessageToView::GroupsForSelection(selection, groups) => write!(
+                f,
+                "MessageToView::GroupsForSelection({}, {} groups)",
+                selection,
+                groups.len()
+            ),
+            MessageToView::StatsForSelection(selection, stats) => {
+                write!(
+                    f,
+                    "MessageToView::StatsForSelection({selection}, {} towns)",
+                    stats.towns
+                )
+            }
+            MessageToView::ImportedList(selection, missing) => {
+                write!(
+                    f,
+                    "MessageToView::ImportedList({selection:?}, {} missing)",
+                    missing.len()
+                )
+            }
+            MessageToView::GradientValuesForSelection(selection, values) => {
+                write!(
+                    f,
+                    "MessageToView::GradientValuesForSelection({selection}, {} values)",
+                    values.values.len()
+                )
+            }
+            MessageToView::HeatmapForSelection(selection, heatmap) => {
+                write!(
+                    f,
+                    "MessageToView::HeatmapForSelection({selection}, {} cells)",
+                    heatmap.cells.len()
+                )
+            }
+            MessageToView::SelectionFailed(selection, err) => {
+                write!(f, "MessageToView::SelectionFailed({selection}, {err:?})")
+            }
             MessageToView::ValueListForConstraint(constraint, selection, towns) => {
                 write!(
                     f,
@@ -63,6 +124,35 @@ impl fmt::Display for MessageToView {
             MessageToView::GhostTowns(towns) => {
                 write!(f, "MessageToView::GhostTowns({} towns)", towns.len())
             }
+            MessageToView::ChangedTowns(towns) => {
+                write!(f, "MessageToView::ChangedTowns({} towns)", towns.len())
+            }
+            MessageToView::FreeSlots(slots) => {
+                write!(f, "MessageToView::FreeSlots({} slots)", slots.len())
+            }
+            MessageToView::Islands(islands) => {
+                write!(f, "MessageToView::Islands({} islands)", islands.len())
+            }
+            MessageToView::Territory(radius, territory) => {
+                write!(
+                    f,
+                    "MessageToView::Territory({radius}, {} alliances)",
+                    territory.alliances.len()
+                )
+            }
+            MessageToView::TownDetails(town_id, details) => {
+                write!(
+                    f,
+                    "MessageToView::TownDetails({town_id}, found: {})",
+                    matches!(details, Ok(Some(_)))
+                )
+            }
+            MessageToView::GotBaseline => {
+                write!(f, "MessageToView::GotBaseline")
+            }
+            MessageToView::GotActivity => {
+                write!(f, "MessageToView::GotActivity")
+            }
             MessageToView::BackendCrashed(err) => {
                 write!(f, "MessageToView::BackendCrashed({err:?})")
             }
@@ -76,6 +166,12 @@ impl fmt::Display for MessageToView {
                     removed_paths.len()
                 )
             }
+            MessageToView::ImageExportProgress(share) => {
+                write!(f, "MessageToView::ImageExportProgress({share})")
+            }
+            MessageToView::ImageExported(result) => {
+                write!(f, "MessageToView::ImageExported({result:?})")
+            }
             MessageToView::VersionInfo(version, message) => {
                 write!(f, "MessageToView::VersionInfo({version}, {message})")
             }
@@ -88,6 +184,13 @@ pub enum MessageToModel {
     SetServer(Server, egui::Context),
     FetchAll,
     FetchGhosts,
+    FetchChanges,
+    FetchFreeSlots(FreeSlotFilter),
+    FetchIslands,
+    FetchTerritory(u8),
+    FetchTownDetails(i32),
+    SetBaseline(Option<PathBuf>),
+    ImportList(String, Vec<ListEntry>),
     FetchTowns(
         EmptyTownSelection,
         HashSet<EmptyConstraint>,
@@ -96,6 +199,8 @@ pub enum MessageToModel {
     LoadDataFromFile(PathBuf, egui::Context),
     DiscoverSavedDatabases,
     MaxCacheSize(CacheSize),
+    InactivityWindow(InactivityWindow),
+    ExportImage(Scene, ImageFormat, PathBuf, egui::Context),
 }
 
 impl fmt::Display for MessageToModel {
@@ -118,6 +223,31 @@ impl fmt::Display for MessageToModel {
             MessageToModel::FetchGhosts => {
                 write!(f, "MessageToModel::FetchGhosts")
             }
+            MessageToModel::FetchChanges => {
+                write!(f, "MessageToModel::FetchChanges")
+            }
+            MessageToModel::FetchFreeSlots(filter) => {
+                write!(f, "MessageToModel::FetchFreeSlots({filter:?})")
+            }
+            MessageToModel::FetchIslands => {
+                write!(f, "MessageToModel::FetchIslands")
+            }
+            MessageToModel::FetchTerritory(radius) => {
+                write!(f, "MessageToModel::FetchTerritory({radius})")
+            }
+            MessageToModel::FetchTownDetails(town_id) => {
+                write!(f, "MessageToModel::FetchTownDetails({town_id})")
+            }
+            MessageToModel::SetBaseline(path) => {
+                write!(f, "MessageToModel::SetBaseline({path:?})")
+            }
+            MessageToModel::ImportList(name, entries) => {
+                write!(
+                    f,
+                    "MessageToModel::ImportList({name}, {} entries)",
+                    entries.len()
+                )
+            }
             MessageToModel::LoadDataFromFile(path, _ctx) => {
                 write!(f, "MessageToModel::LoadDataFromFile({path:?})")
             }
@@ -127,6 +257,18 @@ impl fmt::Display for MessageToModel {
             MessageToModel::MaxCacheSize(x) => {
                 write!(f, "MessageToModel::MaxCacheSize({})", x.to_string())
             }
+            MessageToModel::InactivityWindow(x) => {
+                write!(f, "MessageToModel::InactivityWindow({})", x.to_string())
+            }
+            MessageToModel::ExportImage(scene, format, path, _ctx) => {
+                write!(
+                    f,
+                    "MessageToModel::ExportImage({} shapes, {}, {})",
+                    scene.shapes.len(),
+                    format.name(),
+                    path.display()
+                )
+            }
         }
     }
 }
@@ -0,0 +1,142 @@
+use super::database::Database;
+use crate::storage::{self, SavedDB};
+use anyhow::Context;
+use std::collections::HashMap;
+use time::{Duration, OffsetDateTime};
+
+/// How the points of a player developed over the saved snapshots, starting from the loaded one
+/// and going back in time.
+struct PlayerHistory {
+    /// points in the most recent snapshot that did not show any growth yet
+    points: i64,
+    /// date of that snapshot
+    date: OffsetDateTime,
+    /// date of the snapshot where the player was last seen growing, if we found it
+    last_growth: Option<OffsetDateTime>,
+}
+
+/// Detect inactive players by comparing the loaded snapshot with older snapshots of the same
+/// server. A player counts as inactive for as long as their points have not grown.
+impl Database {
+    /// The activity table has to exist for every connection, even if there is no history.
+    pub(super) fn create_tables_activity(connection: &rusqlite::Connection) -> anyhow::Result<()> {
+        connection
+            .execute(
+                "CREATE TEMP TABLE IF NOT EXISTS player_activity(
+                player_id INTEGER UNIQUE PRIMARY KEY,
+                hours_inactive INTEGER,
+                last_growth TEXT)",
+                (),
+            )
+            .context("Failed to create table player_activity")?;
+        Ok(())
+    }
+
+    fn read_player_points(&self, schema: &str) -> anyhow::Result<HashMap<i32, i64>> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("SELECT player_id, points FROM {schema}.players"))
+            .with_context(|| {
+                format!("Failed to read player points from {schema} (build statement)")
+            })?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .with_context(|| format!("Failed to read player points from {schema} (perform query)"))?
+            .collect::<Result<HashMap<i32, i64>, rusqlite::Error>>()
+            .with_context(|| format!("Failed to read player points from {schema} (read rows)"))?;
+        Ok(rows)
+    }
+
+    /// Fill the activity table from all saved snapshots of this server that are at most
+    /// `lookback` older than the loaded one.
+    pub fn compute_activity(&mut self, lookback: Duration) -> anyhow::Result<()> {
+        self.connection
+            .execute("DELETE FROM player_activity", ())
+            .context("Failed to clear table player_activity")?;
+
+        // databases that only live in memory have no date and no history to compare against
+        let Some(path) = self.path.clone() else {
+            return Ok(());
+        };
+        let current = SavedDB::from(path);
+        let mut history: Vec<SavedDB> = storage::get_list_of_saved_dbs()
+            .remove(current.server())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|saved_db| saved_db.date < current.date)
+            .filter(|saved_db| current.date - saved_db.date <= lookback)
+            .collect();
+        history.sort_by_key(|saved_db| std::cmp::Reverse(saved_db.date));
+        if history.is_empty() {
+            return Ok(());
+        }
+
+        let mut players: HashMap<i32, PlayerHistory> = self
+            .read_player_points("main")?
+            .into_iter()
+            .map(|(player_id, points)| {
+                (
+                    player_id,
+                    PlayerHistory {
+                        points,
+                        date: current.date,
+                        last_growth: None,
+                    },
+                )
+            })
+            .collect();
+
+        for saved_db in &history {
+            self.connection
+                .execute(
+                    "ATTACH DATABASE ?1 AS history",
+                    [saved_db.path.to_string_lossy()],
+                )
+                .with_context(|| {
+                    format!("Failed to attach {} as history", saved_db.path.display())
+                })?;
+            let old_points = self.read_player_points("history");
+            self.connection
+                .execute("DETACH DATABASE history", ())
+                .context("Failed to detach history")?;
+            let old_points = old_points?;
+
+            for (player_id, player) in players
+                .iter_mut()
+                .filter(|(_, player)| player.last_growth.is_none())
+            {
+                match old_points.get(player_id) {
+                    // grew between this snapshot and the next newer one, or did not exist yet
+                    Some(&points) if points < player.points => {
+                        player.last_growth = Some(player.date);
+                    }
+                    None => player.last_growth = Some(player.date),
+                    Some(&points) => {
+                        player.points = points;
+                        player.date = saved_db.date;
+                    }
+                }
+            }
+        }
+
+        let transaction = self
+            .connection
+            .transaction()
+            .context("Failed to start transaction for table player_activity")?;
+        let mut prepared_statement = transaction
+            .prepare("INSERT INTO player_activity VALUES(?1, ?2, ?3)")
+            .context("Failed to prepare statement for player_activity")?;
+        for (player_id, player) in players {
+            let since = player.last_growth.unwrap_or(player.date);
+            let last_growth = player.last_growth.map(storage::format_date);
+            prepared_statement
+                .execute((player_id, (current.date - since).whole_hours(), last_growth))
+                .with_context(|| format!("Failed to insert activity of player {player_id}"))?;
+        }
+        drop(prepared_statement);
+        transaction
+            .commit()
+            .context("Failed to commit transaction for table player_activity")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use super::database::Database;
+use anyhow::Context;
+use std::path::Path;
+
+/// Compare the loaded snapshot against an older (or newer) one. The other snapshot is attached
+/// to the connection as the `baseline` schema and the differences are stored in temporary
+/// tables, so they can be joined and queried like every other attribute of a town.
+impl Database {
+    /// The change tables have to exist for every connection, even if no baseline is attached.
+    /// Without a baseline they stay empty and all change attributes are NULL.
+    pub(super) fn create_tables_changes(connection: &rusqlite::Connection) -> anyhow::Result<()> {
+        connection
+            .execute(
+                "CREATE TEMP TABLE IF NOT EXISTS town_changes(
+                town_id INTEGER UNIQUE PRIMARY KEY,
+                owner_changed INTEGER,
+                founded INTEGER,
+                became_ghost INTEGER)",
+                (),
+            )
+            .context("Failed to create table town_changes")?;
+        connection
+            .execute(
+                "CREATE TEMP TABLE IF NOT EXISTS player_changes(
+                player_id INTEGER UNIQUE PRIMARY KEY,
+                points_change INTEGER,
+                alliance_changed INTEGER)",
+                (),
+            )
+            .context("Failed to create table player_changes")?;
+        Ok(())
+    }
+
+    fn baseline_attached(&self) -> anyhow::Result<bool> {
+        let mut statement = self
+            .connection
+            .prepare("PRAGMA database_list")
+            .context("Failed to list attached databases")?;
+        let names = statement
+            .query_map([], |row| row.get::<usize, String>(1))
+            .context("Failed to list attached databases")?
+            .collect::<Result<Vec<String>, rusqlite::Error>>()
+            .context("Failed to read the names of the attached databases")?;
+        Ok(names.iter().any(|name| name == "baseline"))
+    }
+
+    /// Replace the current baseline with the snapshot at `path`, or remove it if `path` is None.
+    pub fn set_baseline(&mut self, path: Option<&Path>) -> anyhow::Result<()> {
+        if self.baseline_attached()? {
+            self.connection
+                .execute("DETACH DATABASE baseline", ())
+                .context("Failed to detach the previous baseline")?;
+        }
+
+        let transaction = self
+            .connection
+            .transaction()
+            .context("Failed to start transaction for the baseline comparison")?;
+        transaction
+            .execute("DELETE FROM town_changes", ())
+            .context("Failed to clear table town_changes")?;
+        transaction
+            .execute("DELETE FROM player_changes", ())
+            .context("Failed to clear table player_changes")?;
+        transaction
+            .commit()
+            .context("Failed to commit transaction for the baseline comparison")?;
+
+        let Some(path) = path else {
+            return Ok(());
+        };
+
+        self.connection
+            .execute("ATTACH DATABASE ?1 AS baseline", [path.to_string_lossy()])
+            .with_context(|| format!("Failed to attach {} as baseline", path.display()))?;
+
+        let transaction = self
+            .connection
+            .transaction()
+            .context("Failed to start transaction for the baseline comparison")?;
+        transaction
+            .execute(
+                "INSERT INTO town_changes
+                SELECT
+                    towns.town_id,
+                    CASE WHEN old.town_id IS NULL OR towns.player_id IS old.player_id THEN 0 ELSE 1 END,
+                    CASE WHEN old.town_id IS NULL THEN 1 ELSE 0 END,
+                    CASE WHEN old.player_id IS NOT NULL AND towns.player_id IS NULL THEN 1 ELSE 0 END
+                FROM main.towns LEFT JOIN baseline.towns AS old ON (towns.town_id = old.town_id)",
+                (),
+            )
+            .context("Failed to fill table town_changes")?;
+        transaction
+            .execute(
+                "INSERT INTO player_changes
+                SELECT
+                    players.player_id,
+                    players.points - old.points,
+                    CASE WHEN old.player_id IS NULL THEN NULL
+                        WHEN players.alliance_id IS old.alliance_id THEN 0 ELSE 1 END
+                FROM main.players LEFT JOIN baseline.players AS old ON (players.player_id = old.player_id)",
+                (),
+            )
+            .context("Failed to fill table player_changes")?;
+        transaction
+            .commit()
+            .context("Failed to commit transaction for the baseline comparison")?;
+
+        Ok(())
+    }
+}
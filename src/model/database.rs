@@ -1,104 +1,275 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::{Context, Result};
-use runtime_format::FormatArgs;
-use rusqlite::Statement;
+use rusqlite::{OptionalExtension, Statement};
 
 use crate::constraint::Comparator;
 use crate::emptyconstraint::EmptyConstraint;
 use crate::emptyselection::EmptyTownSelection;
+use crate::freeslot::{FreeSlot, FreeSlotFilter, Resource};
+use crate::island::{self, Island};
+use crate::model::towns_joined::{TOWN_SELECTION, TOWN_TABLES};
 use crate::model::ConstraintType;
-use crate::town::Town;
+use crate::selection::Grouping;
+use crate::town::{Town, TownDetails, TownGroup};
 
 pub struct Database {
     pub connection: rusqlite::Connection,
+    /// None if the database only lives in memory
+    pub path: Option<PathBuf>,
 }
 
 pub trait ToSqlFragment {
-    fn to_sql_fragment(&self, parameter_index: usize) -> String;
+    fn to_sql_fragment(&self, query: &mut QueryParts) -> anyhow::Result<String>;
+}
+
+/// Everything a query needs besides its own WHERE clause: the selections it references, each one
+/// as a common table expression, and the values for the numbered parameters.
+pub struct QueryParts<'a> {
+    all_selections: &'a [EmptyTownSelection],
+    /// name and SQL of each common table expression, in the order they have to be defined in
+    ctes: Vec<(String, String)>,
+    /// the referenced selection and the selected column for each entry in `ctes`
+    cte_keys: Vec<(String, String)>,
+    /// selections whose common table expression is currently being built
+    in_progress: Vec<String>,
+    parameters: Vec<String>,
+}
+
+impl<'a> QueryParts<'a> {
+    fn new(all_selections: &'a [EmptyTownSelection]) -> Self {
+        Self {
+            all_selections,
+            ctes: Vec::new(),
+            cte_keys: Vec::new(),
+            in_progress: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    /// Add a value to the list of parameters and return the placeholder for it
+    fn bind(&mut self, value: &str) -> String {
+        self.parameters.push(value.to_string());
+        format!("?{}", self.parameters.len())
+    }
+
+    fn where_clause<SQL>(
+        &mut self,
+        filter_clauses: &[SQL],
+        join_mode: &str,
+    ) -> anyhow::Result<String>
+    where
+        SQL: ToSqlFragment,
+    {
+        // a selection without constraints contains no towns
+        if filter_clauses.is_empty() {
+            return Ok(String::from("(false)"));
+        }
+        let sql_fragments = filter_clauses
+            .iter()
+            .map(|x| x.to_sql_fragment(self))
+            .collect::<anyhow::Result<Vec<String>>>()?
+            .join(&format!(" \n{join_mode} "));
+        // The parentheses keep the user defined constraints together, so anything that
+        // comes after them applies to all of them.
+        Ok(format!("({sql_fragments})"))
+    }
+
+    /// The name of a common table expression that selects `column` from all towns of the
+    /// selection called `name`, or None if there is no such selection.
+    fn selection_cte(&mut self, name: &str, column: &str) -> anyhow::Result<Option<String>> {
+        let key = (name.to_string(), column.to_string());
+        if let Some(index) = self.cte_keys.iter().position(|other| *other == key) {
+            return Ok(Some(self.ctes[index].0.clone()));
+        }
+
+        let all_selections = self.all_selections;
+        let Some(selection) = all_selections
+            .iter()
+            .find(|selection| selection.name == name)
+        else {
+            return Ok(None);
+        };
+        if self.in_progress.contains(&key.0) {
+            return Err(anyhow::anyhow!(
+                "The selection {name} references itself, directly or indirectly"
+            ));
+        }
+
+        self.in_progress.push(key.0.clone());
+        let where_clause = self
+            .where_clause(
+                &selection.constraints,
+                &selection.constraint_join_mode.as_sql(),
+            )
+            .with_context(|| format!("Failed to build the query for the selection {name}"))?;
+        self.in_progress.pop();
+
+        let cte_name = format!("selection_{}", self.ctes.len());
+        self.ctes.push((
+            cte_name.clone(),
+            format!("SELECT {column} {TOWN_TABLES} \nWHERE {where_clause}"),
+        ));
+        self.cte_keys.push(key);
+        Ok(Some(cte_name))
+    }
 }
 
 struct GhostTown;
 impl ToSqlFragment for GhostTown {
-    fn to_sql_fragment(&self, _parameter_index: usize) -> String {
-        "towns.player_id IS NULL".into()
+    fn to_sql_fragment(&self, _query: &mut QueryParts) -> anyhow::Result<String> {
+        Ok("towns.player_id IS NULL".into())
+    }
+}
+
+struct ChangedTown;
+impl ToSqlFragment for ChangedTown {
+    fn to_sql_fragment(&self, _query: &mut QueryParts) -> anyhow::Result<String> {
+        Ok(
+            "town_changes.owner_changed = 1 OR town_changes.founded = 1 OR town_changes.became_ghost = 1"
+                .into(),
+        )
     }
 }
 
 struct AllTowns;
 impl ToSqlFragment for AllTowns {
-    fn to_sql_fragment(&self, _parameter_index: usize) -> String {
-        "true".into()
+    fn to_sql_fragment(&self, _query: &mut QueryParts) -> anyhow::Result<String> {
+        Ok("true".into())
     }
 }
 
 impl ToSqlFragment for EmptyConstraint {
-    fn to_sql_fragment(&self, parameter_index: usize) -> String {
+    fn to_sql_fragment(&self, query: &mut QueryParts) -> anyhow::Result<String> {
         match self.comparator {
             Comparator::LessThan
             | Comparator::Equal
             | Comparator::GreaterThan
-            | Comparator::NotEqual => {
-                format!(
-                    "{}.{} {} ?{}",
-                    self.constraint_type.table(),
-                    self.constraint_type.property(),
+            | Comparator::NotEqual => Ok(format!(
+                "{} {} {}",
+                self.constraint_type.column(),
+                self.comparator.as_sql(),
+                query.bind(&self.value)
+            )),
+            Comparator::InList => {
+                // the whole list is one parameter, so long lists run into neither the limit on
+                // parameters nor the one on the depth of expressions
+                let values = self
+                    .list_values()
+                    .map(|value| {
+                        if self.constraint_type.is_string() {
+                            Ok(serde_json::Value::from(value))
+                        } else {
+                            serde_json::from_str::<serde_json::Number>(value)
+                                .map(serde_json::Value::from)
+                                .with_context(|| format!("The list entry {value} is not a number"))
+                        }
+                    })
+                    .collect::<anyhow::Result<Vec<serde_json::Value>>>()?;
+                let list = serde_json::to_string(&values).context("Failed to encode the list")?;
+                Ok(format!(
+                    "{} {} (SELECT value FROM json_each({}))",
+                    self.constraint_type.column(),
                     self.comparator.as_sql(),
-                    parameter_index + 1
-                )
+                    query.bind(&list)
+                ))
             }
             Comparator::InSelection | Comparator::NotInSelection => {
-                format!(
-                    "{}.{} {} ({{{}}})",
-                    self.constraint_type.table(),
-                    self.constraint_type.property(),
+                let ct_column = self.constraint_type.column();
+                if self.value.is_empty() {
+                    // no selection referenced (yet), so this constraint does not restrict anything
+                    return Ok(String::from("true"));
+                }
+                match query.selection_cte(&self.value, &ct_column)? {
+                    Some(cte_name) => Ok(format!(
+                        "{ct_column} {} (SELECT * FROM {cte_name})",
+                        self.comparator.as_sql()
+                    )),
+                    // The user typed in a selection name that does not exist. If the user wants towns that are IN
+                    // this imaginary selection, they'll get none. If they want all town that are NOT IN this imaginary
+                    // selection they'll get all possible ones (an empty selection does not restrict the search in any way)
+                    None if self.comparator == Comparator::NotInSelection => {
+                        Ok(String::from("true"))
+                    }
+                    None => Ok(String::from("false")),
+                }
+            }
+            Comparator::Top
+            | Comparator::Bottom
+            | Comparator::TopPerPlayer
+            | Comparator::BottomPerPlayer
+            | Comparator::TopPerAlliance
+            | Comparator::BottomPerAlliance => {
+                // the towns among which we rank
+                let (count, scope) = self.rank_limit();
+                let scope_clause = match scope {
+                    None => String::new(),
+                    Some(scope) => match query.selection_cte(&scope, "towns.town_id")? {
+                        Some(cte_name) => {
+                            format!("AND towns.town_id IN (SELECT * FROM {cte_name})")
+                        }
+                        // ranking within a selection that does not exist yields no towns, just like IN
+                        None => return Ok(String::from("false")),
+                    },
+                };
+
+                // DENSE_RANK gives towns with the same value the same position. That way "top 20
+                // by player points" returns the towns of the 20 biggest players, not 20 towns.
+                let ct_column = self.constraint_type.column();
+                Ok(format!(
+                    "towns.town_id {} (SELECT ranked.town_id FROM (SELECT towns.town_id, \n\
+                        DENSE_RANK() OVER ({} ORDER BY {ct_column} {}) AS position \n\
+                        {TOWN_TABLES} \n\
+                        WHERE {ct_column} IS NOT NULL {scope_clause}) AS ranked \n\
+                    WHERE ranked.position <= {count})",
                     self.comparator.as_sql(),
-                    parameter_index + 1
-                )
+                    self.comparator.rank_partition_sql(),
+                    self.comparator.rank_order_sql(),
+                ))
             }
         }
     }
 }
 
-static TOWN_SELECTION: &str =
-    "towns.*, offsets.offset_x, offsets.offset_y, players.name, alliances.name";
-
 impl Database {
+    /// Build the SQL text of a query and the values for its parameters. Referenced selections
+    /// are resolved recursively, so `all_selections` may be empty if there are none.
     fn construct_sql<SQL>(
         selection_clause: &str,
         filter_clauses: &[SQL],
         join_mode: &str,
         order_clause: Option<&str>,
-    ) -> String
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<(String, Vec<String>)>
     where
         SQL: ToSqlFragment,
     {
-        let sql_start = format!(
-            "SELECT {selection_clause} from \n\
-                towns \n\
-                LEFT JOIN islands ON (towns.island_x = islands.x AND towns.island_y = islands.y) \n\
-                LEFT JOIN offsets ON (towns.slot_number = offsets.slot_number) \n\
-                LEFT JOIN players ON (towns.player_id = players.player_id) \n\
-                LEFT JOIN alliances ON (players.alliance_id = alliances.alliance_id) \n\
-                WHERE islands.type = offsets.type AND \n",
-        );
-
-        let sql_fragments = filter_clauses
-            .iter()
-            .enumerate()
-            .map(|(i, x)| x.to_sql_fragment(i))
-            .collect::<Vec<String>>()
-            .join(&format!(" \n{join_mode} "));
+        let mut query = QueryParts::new(all_selections);
+        let where_clause = query.where_clause(filter_clauses, join_mode)?;
 
+        let sql_with = if query.ctes.is_empty() {
+            String::new()
+        } else {
+            let ctes = query
+                .ctes
+                .iter()
+                .map(|(name, sql)| format!("{name} AS ({sql})"))
+                .collect::<Vec<String>>()
+                .join(", \n");
+            format!("WITH {ctes} \n")
+        };
         let sql_order = if let Some(text) = order_clause {
             String::from(" \n") + text
         } else {
             String::new()
         };
-        // join the different parts together. The parentheses are important, because
-        // they ensure the order of precedence does not mingle the island type join
-        // with the user defined constraints.
-        sql_start + "(" + &sql_fragments + ")" + &sql_order
+
+        let sql = format!(
+            "{sql_with}SELECT {selection_clause} {TOWN_TABLES} \nWHERE {where_clause}{sql_order}"
+        );
+        Ok((sql, query.parameters))
     }
 
     fn sql_to_prepared_statement(&self, sql: &str) -> anyhow::Result<Statement> {
@@ -110,53 +281,20 @@ impl Database {
     fn sql_to_bound_statement<'a>(
         &'a self,
         sql_text: &str,
-        constraints: &[EmptyConstraint],
-        all_selections: &[EmptyTownSelection],
+        parameters: &[String],
     ) -> anyhow::Result<Statement<'a>> {
-        let mut format_mapping = HashMap::new();
-        for (index, constraint) in constraints.iter().enumerate() {
-            if constraint.referenced_selection().is_some() {
-                format_mapping.insert(
-                    (index + 1).to_string(),
-                    constraint.get_sql_value(self, all_selections),
-                );
-            }
-        }
-        let sql_text = FormatArgs::new(sql_text, &format_mapping).to_string();
-        let mut statement = self.sql_to_prepared_statement(&sql_text)?;
-
-        for (index, constraint) in constraints.iter().enumerate() {
-            if constraint.referenced_selection().is_none() {
-                statement.raw_bind_parameter(
-                    index + 1,
-                    &constraint.get_sql_value(self, all_selections),
-                )?;
-            }
+        let mut statement = self.sql_to_prepared_statement(sql_text)?;
+        for (index, value) in parameters.iter().enumerate() {
+            statement
+                .raw_bind_parameter(index + 1, value)
+                .with_context(|| format!("Failed to bind parameter {} to {value}", index + 1))?;
         }
         Ok(statement)
     }
 
-    pub fn selection_to_sql(
-        &self,
-        selection_clause: &str,
-        selection: &EmptyTownSelection,
-        all_selections: &[EmptyTownSelection],
-    ) -> anyhow::Result<String> {
-        let sql = Self::construct_sql(
-            selection_clause,
-            &selection.constraints,
-            &selection.constraint_join_mode.as_sql(),
-            None,
-        );
-        let statement =
-            self.sql_to_bound_statement(&sql, &selection.constraints, all_selections)?;
-        statement.expanded_sql().ok_or(anyhow::Error::msg(
-            "Failed to convert the selection {selection} into an SQL string",
-        ))
-    }
-
     pub fn get_all_towns(&self) -> anyhow::Result<Vec<Town>> {
-        let sql = Self::construct_sql(TOWN_SELECTION, &[AllTowns], "and", None);
+        let (sql, _parameters) =
+            Self::construct_sql(TOWN_SELECTION, &[AllTowns], "and", None, &[])?;
         let mut statement = self.sql_to_prepared_statement(&sql)?;
         let rows = statement
             .query([])
@@ -169,7 +307,8 @@ impl Database {
     }
 
     pub fn get_ghost_towns(&self) -> anyhow::Result<Vec<Town>> {
-        let sql = Self::construct_sql(TOWN_SELECTION, &[GhostTown], "and", None);
+        let (sql, _parameters) =
+            Self::construct_sql(TOWN_SELECTION, &[GhostTown], "and", None, &[])?;
         let mut statement = self.sql_to_prepared_statement(&sql)?;
         let rows = statement
             .query([])
@@ -181,6 +320,109 @@ impl Database {
         Ok(rows)
     }
 
+    pub fn get_changed_towns(&self) -> anyhow::Result<Vec<Town>> {
+        let (sql, _parameters) =
+            Self::construct_sql(TOWN_SELECTION, &[ChangedTown], "and", None, &[])?;
+        let mut statement = self.sql_to_prepared_statement(&sql)?;
+        let rows = statement
+            .query([])
+            .context("Failed to get changed towns from the database (perform query)")?
+            .mapped(Town::from)
+            .collect::<std::result::Result<Vec<Town>, rusqlite::Error>>()
+            .context("Failed to create a town from row")?;
+
+        Ok(rows)
+    }
+
+    /// A single town with its player and alliance, None if there is no such town
+    pub fn get_town_details(&self, town_id: i32) -> anyhow::Result<Option<TownDetails>> {
+        let sql = format!(
+            "SELECT {TOWN_SELECTION}, towns.player_points, towns.player_rank, towns.player_towns, \n\
+                towns.alliance_points, towns.alliance_rank, towns.alliance_members \n\
+            {TOWN_TABLES} \n\
+            WHERE towns.town_id = ?1"
+        );
+        let mut statement = self.sql_to_prepared_statement(&sql)?;
+        statement
+            .query_row([town_id], TownDetails::from)
+            .optional()
+            .with_context(|| format!("Failed to get the details of town {town_id}"))
+    }
+
+    /// Every island, with the outline of its island type
+    pub fn get_islands(&self) -> anyhow::Result<Vec<Island>> {
+        let mut statement =
+            self.sql_to_prepared_statement("SELECT type, offset_x, offset_y FROM offsets")?;
+        let offsets = statement
+            .query([])
+            .context("Failed to get the island offsets from the database (perform query)")?
+            .mapped(|row| {
+                Ok((
+                    row.get::<usize, u16>(0)?,
+                    egui::vec2(
+                        row.get::<usize, f32>(1)? / 125.0,
+                        row.get::<usize, f32>(2)? / 125.0,
+                    ),
+                ))
+            })
+            .collect::<std::result::Result<Vec<_>, rusqlite::Error>>()
+            .context("Failed to read an island offset from row")?;
+        let outlines = island::outlines(&offsets);
+
+        let no_outline = Arc::new(Vec::new());
+        let mut statement = self.sql_to_prepared_statement("SELECT x, y, type FROM islands")?;
+        let islands = statement
+            .query([])
+            .context("Failed to get islands from the database (perform query)")?
+            .mapped(|row| {
+                Ok(Island {
+                    x: row.get(0)?,
+                    y: row.get(1)?,
+                    outline: outlines
+                        .get(&row.get::<usize, u16>(2)?)
+                        .cloned()
+                        .unwrap_or_else(|| no_outline.clone()),
+                })
+            })
+            .collect::<std::result::Result<Vec<Island>, rusqlite::Error>>()
+            .context("Failed to create an island from row")?;
+
+        Ok(islands)
+    }
+
+    /// All island slots that are not taken by a town and match the filter.
+    pub fn get_free_slots(&self, filter: &FreeSlotFilter) -> anyhow::Result<Vec<FreeSlot>> {
+        let (island_x, island_y) = filter.island().unzip();
+        let mut statement = self.sql_to_prepared_statement(
+            "SELECT islands.x, islands.y, offsets.slot_number, offsets.offset_x, offsets.offset_y, \n\
+                (islands.x / 100) * 10 + (islands.y / 100) AS ocean \n\
+            FROM islands \n\
+            JOIN offsets ON (islands.type = offsets.type) \n\
+            LEFT JOIN towns ON (towns.island_x = islands.x AND towns.island_y = islands.y \n\
+                AND towns.slot_number = offsets.slot_number) \n\
+            WHERE towns.town_id IS NULL \n\
+            AND (?1 IS NULL OR ocean = ?1) \n\
+            AND (?2 IS NULL OR islands.x = ?2) \n\
+            AND (?3 IS NULL OR islands.y = ?3) \n\
+            AND (?4 IS NULL OR islands.ressource_plus = ?4) \n\
+            AND (?5 IS NULL OR islands.ressource_minus = ?5)",
+        )?;
+        let rows = statement
+            .query((
+                filter.ocean,
+                island_x,
+                island_y,
+                filter.ressource_plus.map(Resource::as_sql),
+                filter.ressource_minus.map(Resource::as_sql),
+            ))
+            .context("Failed to get free slots from the database (perform query)")?
+            .mapped(FreeSlot::from)
+            .collect::<std::result::Result<Vec<FreeSlot>, rusqlite::Error>>()
+            .context("Failed to create a free slot from row")?;
+
+        Ok(rows)
+    }
+
     pub fn get_names_for_constraint_type(
         &self,
         constraint_type: ConstraintType,
@@ -188,13 +430,14 @@ impl Database {
         let ct_property = constraint_type.property();
         let ct_table = constraint_type.table();
 
+        // derived attributes are NULL for towns without a player or alliance
         let statement_text = if constraint_type.is_string() {
             format!(
-                "SELECT DISTINCT {ct_table}.{ct_property} from {ct_table} ORDER BY LOWER({ct_table}.{ct_property})",
+                "SELECT DISTINCT {ct_table}.{ct_property} from {ct_table} WHERE {ct_table}.{ct_property} IS NOT NULL ORDER BY LOWER({ct_table}.{ct_property})",
             )
         } else {
             format!(
-                "SELECT DISTINCT {ct_table}.{ct_property} from {ct_table} ORDER BY {ct_table}.{ct_property}"
+                "SELECT DISTINCT {ct_table}.{ct_property} from {ct_table} WHERE {ct_table}.{ct_property} IS NOT NULL ORDER BY {ct_table}.{ct_property}"
             )
         };
 
@@ -206,7 +449,7 @@ impl Database {
                 if constraint_type.is_string() {
                     row.get::<usize, String>(0)
                 } else {
-                    row.get::<usize, usize>(0).map(|value| format!("{value}"))
+                    row.get::<usize, i64>(0).map(|value| format!("{value}"))
                 }
             })
             .collect::<std::result::Result<Vec<String>, rusqlite::Error>>()
@@ -226,30 +469,30 @@ impl Database {
             return self.get_names_for_constraint_type(constraint_type);
         }
 
-        let ct_property = constraint_type.property();
-        let ct_table = constraint_type.table();
+        let ct_column = constraint_type.column();
         let order_clause = if constraint_type.is_string() {
-            format!("ORDER BY LOWER({ct_table}.{ct_property})")
+            format!("ORDER BY LOWER({ct_column})")
         } else {
-            format!("ORDER BY {ct_table}.{ct_property}")
+            format!("ORDER BY {ct_column}")
         };
-        let sql = Self::construct_sql(
-            &format!("DISTINCT {ct_table}.{ct_property}"),
+        let (sql, parameters) = Self::construct_sql(
+            &format!("DISTINCT {ct_column}"),
             constraints,
             join_mode,
             Some(&order_clause),
-        );
+            all_selections,
+        )?;
 
-        let mut statement = self.sql_to_bound_statement(&sql, constraints, all_selections)?;
+        let mut statement = self.sql_to_bound_statement(&sql, &parameters)?;
         let rows = statement
             .raw_query()
             .mapped(|row| {
                 if constraint_type.is_string() {
-                    row.get::<usize, String>(0)
+                    row.get::<usize, Option<String>>(0)
                 } else {
-                    let value_option = row.get::<usize, usize>(0);
+                    let value_option = row.get::<usize, Option<i64>>(0);
                     match value_option {
-                        Ok(value) => Ok(format!("{value}")),
+                        Ok(value) => Ok(value.map(|value| format!("{value}"))),
                         Err(err) => {
                             eprintln!("{err:?}");
                             Err(err)
@@ -258,6 +501,7 @@ impl Database {
                 }
             })
             .filter_map(Result::ok)
+            .flatten()
             .collect();
 
         Ok(rows)
@@ -273,8 +517,9 @@ impl Database {
             return Ok(Vec::new());
         }
 
-        let sql = Self::construct_sql(TOWN_SELECTION, constraints, join_mode, None);
-        let mut statement = self.sql_to_bound_statement(&sql, constraints, all_selections)?;
+        let (sql, parameters) =
+            Self::construct_sql(TOWN_SELECTION, constraints, join_mode, None, all_selections)?;
+        let mut statement = self.sql_to_bound_statement(&sql, &parameters)?;
         let rows = statement
             .raw_query()
             .mapped(Town::from)
@@ -283,4 +528,75 @@ impl Database {
 
         Ok(rows)
     }
+
+    /// The value of a numeric attribute for every town that matches the constraints. Towns
+    /// where the attribute is NULL, e.g. the player points of a ghost town, are left out.
+    pub fn get_gradient_values_for_constraints(
+        &self,
+        constraints: &[EmptyConstraint],
+        join_mode: &str,
+        attribute: ConstraintType,
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<HashMap<i32, f64>> {
+        if constraints.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let column = attribute.column();
+        let (sql, parameters) = Self::construct_sql(
+            &format!("towns.town_id, {column}"),
+            constraints,
+            join_mode,
+            None,
+            all_selections,
+        )?;
+        let mut statement = self.sql_to_bound_statement(&sql, &parameters)?;
+        let rows = statement
+            .raw_query()
+            .mapped(|row| Ok((row.get(0)?, row.get::<usize, Option<f64>>(1)?)))
+            .collect::<std::result::Result<Vec<(i32, Option<f64>)>, rusqlite::Error>>()
+            .context("Failed to read the gradient values from rows")?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(town_id, value)| Some((town_id, value?)))
+            .collect())
+    }
+
+    /// Group the towns that match the constraints by player or by alliance, biggest group first
+    pub fn get_groups_for_constraints(
+        &self,
+        constraints: &[EmptyConstraint],
+        join_mode: &str,
+        grouping: Grouping,
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<Vec<TownGroup>> {
+        let Some(group_by) = grouping.group_by_sql() else {
+            return Ok(Vec::new());
+        };
+        if constraints.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (sql, parameters) = Self::construct_sql(
+            &format!(
+                "{}, COUNT(DISTINCT towns.player_id), COUNT(*), SUM(towns.points)",
+                grouping.name_sql()
+            ),
+            constraints,
+            join_mode,
+            Some(&format!(
+                "GROUP BY {group_by} ORDER BY SUM(towns.points) DESC"
+            )),
+            all_selections,
+        )?;
+        let mut statement = self.sql_to_bound_statement(&sql, &parameters)?;
+        let rows = statement
+            .raw_query()
+            .mapped(TownGroup::from)
+            .collect::<std::result::Result<Vec<TownGroup>, rusqlite::Error>>()
+            .context("Failed to create a town group from row")?;
+
+        Ok(rows)
+    }
 }
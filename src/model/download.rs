@@ -1,5 +1,6 @@
 use super::database::Database;
 use super::offset_data;
+use super::towns_joined;
 use crate::message::{MessageToView, Progress};
 use anyhow::{Context, Result};
 use reqwest;
@@ -32,9 +33,23 @@ fn make_client() -> reqwest::blocking::Client {
 
 impl Database {
     pub fn load_from_file(path: &Path) -> anyhow::Result<Self> {
-        let conn = rusqlite::Connection::open(path)
+        let mut conn = rusqlite::Connection::open(path)
             .with_context(|| format!("Failed to open database with filename {path:?}"))?;
-        Ok(Self { connection: conn })
+
+        // snapshots saved by older versions do not contain the derived tables yet
+        if !Database::table_exists(&conn, "town_stats")? {
+            Database::create_tables_derived(&mut conn)?;
+        }
+        if !Database::table_exists(&conn, "towns_joined")? {
+            Database::create_table_towns_joined(&mut conn)?;
+        }
+        Database::create_tables_changes(&conn)?;
+        Database::create_tables_activity(&conn)?;
+
+        Ok(Self {
+            connection: conn,
+            path: Some(path.to_path_buf()),
+        })
     }
 
     pub fn create_for_world(
@@ -131,27 +146,15 @@ impl Database {
             .context("Failed to send progressupdate 6 to view")?;
         ctx.request_repaint();
 
-        // TODO for a proper optimization we should build a new table that pre-performs the list of joins we do in every single query.
-        //      only if we want to support more complex queries, which are not based on towns (but instead users or alliances) do we
-        //      even need the DB to be split up into different tables.
-        // The following code did in fact lead to worse performance....
-        // Severely worse performance...
-        // optimize the DB
-        // make the DB automatically optimize. Can't set analysis_limit though, because it crashed the DB
-        // let _result = conn.execute("PRAGMA optimize", [])?;
-        // let _result = conn.query_row("PRAGMA optimize(-1)", [], |row| {
-        //     println!("{row:?}");
-        //     Ok(())
-        // });
-
-        // // create indices
-        // let _result = conn.execute("CREATE INDEX towns_slot ON towns (slot_number ASC);", [])?;
-        // let _result = conn.execute("CREATE INDEX towns_x ON towns (island_x ASC);", [])?;
-        // let _result = conn.execute("CREATE INDEX towns_y ON towns (island_y ASC);", [])?;
-        // let _result = conn.execute("CREATE INDEX islands_x ON islands (x ASC);", [])?;
-        // let _result = conn.execute("CREATE INDEX islands_y ON islands (y ASC);", [])?;
-
-        Ok(Self { connection: conn })
+        Database::create_tables_derived(&mut conn)?;
+        Database::create_table_towns_joined(&mut conn)?;
+        Database::create_tables_changes(&conn)?;
+        Database::create_tables_activity(&conn)?;
+
+        Ok(Self {
+            connection: conn,
+            path: filename.map(Path::to_path_buf),
+        })
     }
 
     fn create_table_players(
@@ -459,4 +462,121 @@ impl Database {
             .context("Failed to commit transaction for table offsets")?;
         Ok(())
     }
+
+    /// Perform the joins every town query needs once, instead of in every query.
+    /// See `towns_joined` for the layout of the table.
+    fn create_table_towns_joined(connection: &mut rusqlite::Connection) -> anyhow::Result<()> {
+        let transaction = connection
+            .transaction()
+            .context("Failed to start transaction for table creation towns_joined")?;
+        transaction
+            .execute(towns_joined::CREATE_TABLE, ())
+            .context("Failed to create table towns_joined")?;
+        transaction
+            .execute(towns_joined::FILL_TABLE, ())
+            .context("Failed to fill table towns_joined")?;
+        for index in towns_joined::CREATE_INDICES {
+            transaction
+                .execute(index, ())
+                .with_context(|| format!("Failed to create index for towns_joined: {index}"))?;
+        }
+        transaction
+            .commit()
+            .context("Failed to commit transaction for table towns_joined")?;
+        Ok(())
+    }
+
+    fn table_exists(connection: &rusqlite::Connection, name: &str) -> anyhow::Result<bool> {
+        connection
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+                [name],
+                |row| row.get(0),
+            )
+            .with_context(|| format!("Failed to check if table {name} exists"))
+    }
+
+    /// Precompute attributes that are not part of the raw data from the server, but can be
+    /// derived from it. Ratios and percentages are rounded to integers, so they behave like
+    /// every other numeric attribute in the constraints and drop down lists.
+    fn create_tables_derived(connection: &mut rusqlite::Connection) -> anyhow::Result<()> {
+        let transaction = connection
+            .transaction()
+            .context("Failed to start transaction for table creation derived")?;
+
+        transaction
+            .execute(
+                "CREATE TABLE town_stats(
+                town_id INTEGER UNIQUE PRIMARY KEY,
+                ocean INTEGER,
+                player_towns_on_island INTEGER,
+                alliance_ocean_share INTEGER)",
+                (),
+            )
+            .context("Failed to create table town_stats")?;
+        transaction
+            .execute(
+                "INSERT INTO town_stats
+                SELECT
+                    town_id,
+                    ocean,
+                    CASE WHEN player_id IS NULL THEN NULL
+                        ELSE COUNT(*) OVER (PARTITION BY player_id, island_x, island_y) END,
+                    CASE WHEN alliance_id IS NULL THEN NULL
+                        ELSE CAST(ROUND(100.0 * COUNT(*) OVER (PARTITION BY ocean, alliance_id)
+                            / COUNT(*) OVER (PARTITION BY ocean)) AS INTEGER) END
+                FROM (
+                    SELECT
+                        towns.town_id,
+                        towns.player_id,
+                        towns.island_x,
+                        towns.island_y,
+                        players.alliance_id,
+                        (towns.island_x / 100) * 10 + (towns.island_y / 100) AS ocean
+                    FROM towns LEFT JOIN players ON (towns.player_id = players.player_id)
+                )",
+                (),
+            )
+            .context("Failed to fill table town_stats")?;
+
+        transaction
+            .execute(
+                "CREATE TABLE player_stats(
+                player_id INTEGER UNIQUE PRIMARY KEY,
+                average_town_points INTEGER)",
+                (),
+            )
+            .context("Failed to create table player_stats")?;
+        transaction
+            .execute(
+                "INSERT INTO player_stats
+                SELECT player_id, CAST(ROUND(AVG(points)) AS INTEGER)
+                FROM towns WHERE player_id IS NOT NULL GROUP BY player_id",
+                (),
+            )
+            .context("Failed to fill table player_stats")?;
+
+        transaction
+            .execute(
+                "CREATE TABLE alliance_stats(
+                alliance_id INTEGER UNIQUE PRIMARY KEY,
+                points_per_member INTEGER)",
+                (),
+            )
+            .context("Failed to create table alliance_stats")?;
+        transaction
+            .execute(
+                "INSERT INTO alliance_stats
+                SELECT alliance_id,
+                    CASE WHEN members > 0 THEN CAST(ROUND(1.0 * points / members) AS INTEGER) ELSE 0 END
+                FROM alliances",
+                (),
+            )
+            .context("Failed to fill table alliance_stats")?;
+
+        transaction
+            .commit()
+            .context("Failed to commit transaction for derived tables")?;
+        Ok(())
+    }
 }
@@ -0,0 +1,53 @@
+use super::database::Database;
+use crate::bbcode::ListEntry;
+use anyhow::Context;
+use rusqlite::OptionalExtension;
+
+/// Look up the entries of a pasted list in the loaded snapshot.
+impl Database {
+    /// Split the entries into the ones that exist in the snapshot and the ones that don't. Names
+    /// are matched regardless of case and replaced by the spelling used in game.
+    pub fn resolve_list(
+        &self,
+        entries: &[ListEntry],
+    ) -> anyhow::Result<(Vec<ListEntry>, Vec<ListEntry>)> {
+        let mut town_statement = self
+            .connection
+            .prepare("SELECT town_id FROM towns_joined WHERE town_id = ?1")
+            .context("Failed to prepare statement to look up towns")?;
+        let mut player_statement = self
+            .connection
+            .prepare("SELECT name FROM players WHERE name = ?1 COLLATE NOCASE")
+            .context("Failed to prepare statement to look up players")?;
+        let mut alliance_statement = self
+            .connection
+            .prepare("SELECT name FROM alliances WHERE name = ?1 COLLATE NOCASE")
+            .context("Failed to prepare statement to look up alliances")?;
+
+        let mut found = Vec::new();
+        let mut missing = Vec::new();
+        for entry in entries {
+            let resolved = match entry {
+                ListEntry::Town(id) => town_statement
+                    .query_row([id], |row| row.get::<usize, i64>(0))
+                    .optional()
+                    .map(|town_id| town_id.map(|town_id| ListEntry::Town(format!("{town_id}")))),
+                ListEntry::Player(name) => player_statement
+                    .query_row([name], |row| row.get(0))
+                    .optional()
+                    .map(|name| name.map(ListEntry::Player)),
+                ListEntry::Alliance(name) => alliance_statement
+                    .query_row([name], |row| row.get(0))
+                    .optional()
+                    .map(|name| name.map(ListEntry::Alliance)),
+            }
+            .with_context(|| format!("Failed to look up {entry}"))?;
+
+            match resolved {
+                Some(resolved) => found.push(resolved),
+                None => missing.push(entry.clone()),
+            }
+        }
+        Ok((found, missing))
+    }
+}
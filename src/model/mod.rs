@@ -1,17 +1,28 @@
+use crate::bbcode::ListEntry;
 use crate::constraint::ConstraintType;
 use crate::emptyconstraint::EmptyConstraint;
 use crate::emptyselection::EmptyTownSelection;
-use crate::selection::AndOr;
-use crate::town::Town;
+use crate::freeslot::{FreeSlot, FreeSlotFilter};
+use crate::gradient::GradientValues;
+use crate::heatmap::{Heatmap, Weighting};
+use crate::island::Island;
+use crate::selection::{AndOr, Grouping};
+use crate::territory::Territory;
+use crate::town::{SelectionStats, Town, TownDetails, TownGroup};
 use eframe::epaint::ahash::HashMap;
 use std::collections::hash_map::Entry;
 use std::collections::BTreeSet;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
+mod activity;
+mod baseline;
 pub(crate) mod database;
 pub mod download;
+mod import;
 mod offset_data;
+mod towns_joined;
 
 const DECAY: f32 = 0.9;
 const MIN_AGE: f32 = 0.1; // anything that was not touched `DECAY.powi(20)` times in a row should be removed from cache
@@ -23,6 +34,12 @@ type StringCacheKey = (
     BTreeSet<EmptyTownSelection>,
 );
 type TownCacheKey = (Vec<EmptyConstraint>, AndOr, BTreeSet<EmptyTownSelection>);
+type GroupCacheKey = (
+    Grouping,
+    Vec<EmptyConstraint>,
+    AndOr,
+    BTreeSet<EmptyTownSelection>,
+);
 
 pub enum Model {
     Uninitialized,
@@ -31,6 +48,7 @@ pub enum Model {
         ctx: egui::Context,
         cache_strings: HashMap<StringCacheKey, (f32, Arc<Vec<String>>)>,
         cache_towns: HashMap<TownCacheKey, (f32, Arc<Vec<Town>>)>,
+        cache_groups: HashMap<GroupCacheKey, (f32, Arc<Vec<TownGroup>>)>,
     },
 }
 
@@ -70,6 +88,7 @@ impl Model {
             Model::Loaded {
                 cache_strings,
                 cache_towns,
+                cache_groups,
                 ..
             } => {
                 // Alternatives to the current aging method could incorporate something between LeastRecentlyUsed cache, time base cache and LeastOftenUsed cache.
@@ -77,6 +96,7 @@ impl Model {
                 age_and_filter_hashmap(cache_strings, keep_count);
                 // print!("Towns  : ");
                 age_and_filter_hashmap(cache_towns, keep_count);
+                age_and_filter_hashmap(cache_groups, keep_count);
             }
         }
     }
@@ -132,6 +152,117 @@ impl Model {
         }
     }
 
+    /// The values a selection with a gradient is coloured by
+    pub fn get_gradient_values_for_constraints(
+        &self,
+        selection: &EmptyTownSelection,
+        constraints: &[EmptyConstraint],
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<Arc<GradientValues>> {
+        match (self, selection.gradient) {
+            (Model::Uninitialized, _) | (_, None) => Ok(Arc::new(GradientValues::default())),
+            (Model::Loaded { db, .. }, Some(gradient)) => {
+                let values = db.get_gradient_values_for_constraints(
+                    constraints,
+                    &selection.constraint_join_mode.as_sql(),
+                    gradient.attribute,
+                    all_selections,
+                )?;
+                Ok(Arc::new(GradientValues::new(values)))
+            }
+        }
+    }
+
+    /// The density of a selection with a heatmap. Derived from the cached list of towns, only
+    /// the player points are looked up.
+    pub fn get_heatmap_for_constraints(
+        &mut self,
+        selection: &EmptyTownSelection,
+        constraints: &[EmptyConstraint],
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<Arc<Heatmap>> {
+        let Some(settings) = selection.heatmap else {
+            return Ok(Arc::new(Heatmap::default()));
+        };
+        let towns = self.get_towns_for_constraints(selection, constraints, all_selections)?;
+        let player_points = match (&*self, settings.weighting) {
+            (Model::Loaded { db, .. }, Weighting::PlayerPoints) => db
+                .get_gradient_values_for_constraints(
+                    constraints,
+                    &selection.constraint_join_mode.as_sql(),
+                    ConstraintType::PlayerPoints,
+                    all_selections,
+                )?,
+            _ => std::collections::HashMap::new(),
+        };
+        let points = towns.iter().map(|town| {
+            let weight = match settings.weighting {
+                Weighting::Count => 1.0,
+                Weighting::TownPoints => f64::from(town.points),
+                // ghost towns have no player, so they don't count
+                Weighting::PlayerPoints => player_points.get(&town.id).copied().unwrap_or(0.0),
+            };
+            (egui::vec2(town.x, town.y), weight)
+        });
+        Ok(Arc::new(Heatmap::new(points, f32::from(settings.radius))))
+    }
+
+    /// Summary of the towns of a selection. Derived from the cached list of towns, so it always
+    /// matches the towns shown on the map.
+    pub fn get_stats_for_constraints(
+        &mut self,
+        selection: &EmptyTownSelection,
+        constraints: &[EmptyConstraint],
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<Arc<SelectionStats>> {
+        let towns = self.get_towns_for_constraints(selection, constraints, all_selections)?;
+        Ok(Arc::new(SelectionStats::from_towns(&towns)))
+    }
+
+    pub fn get_groups_for_constraints(
+        &mut self,
+        selection: &EmptyTownSelection,
+        constraints: &[EmptyConstraint],
+        all_selections: &[EmptyTownSelection],
+    ) -> anyhow::Result<Arc<Vec<TownGroup>>> {
+        match self {
+            Model::Uninitialized => Ok(Arc::new(Vec::new())),
+            Model::Loaded {
+                db, cache_groups, ..
+            } => {
+                let mut cache_selection = selection.clone();
+                cache_selection.constraints = constraints.to_vec();
+
+                let referenced_selections =
+                    cache_selection.all_referenced_selections(all_selections)?;
+
+                let key = (
+                    selection.grouping,
+                    constraints.to_vec(),
+                    selection.constraint_join_mode,
+                    referenced_selections,
+                );
+                let value = match cache_groups.entry(key) {
+                    Entry::Occupied(entry) => {
+                        let tuple = entry.into_mut();
+                        tuple.0 += 1.0;
+                        tuple.1.clone()
+                    }
+                    Entry::Vacant(entry) => {
+                        let value = Arc::new(db.get_groups_for_constraints(
+                            constraints,
+                            &selection.constraint_join_mode.as_sql(),
+                            selection.grouping,
+                            all_selections,
+                        )?);
+                        entry.insert((1.0, value)).1.clone()
+                    }
+                };
+                Ok(value)
+            }
+        }
+    }
+
     pub fn get_names_for_constraint_with_constraints(
         &mut self,
         selection: &EmptyTownSelection,
@@ -184,6 +315,97 @@ impl Model {
         }
     }
 
+    pub fn get_free_slots(&self, filter: &FreeSlotFilter) -> anyhow::Result<Arc<Vec<FreeSlot>>> {
+        match self {
+            Model::Uninitialized => Ok(Arc::new(Vec::new())),
+            Model::Loaded { db, .. } => Ok(Arc::new(db.get_free_slots(filter)?)),
+        }
+    }
+
+    pub fn get_town_details(&self, town_id: i32) -> anyhow::Result<Option<Arc<TownDetails>>> {
+        match self {
+            Model::Uninitialized => Ok(None),
+            Model::Loaded { db, .. } => Ok(db.get_town_details(town_id)?.map(Arc::new)),
+        }
+    }
+
+    pub fn get_islands(&self) -> anyhow::Result<Arc<Vec<Island>>> {
+        match self {
+            Model::Uninitialized => Ok(Arc::new(Vec::new())),
+            Model::Loaded { db, .. } => Ok(Arc::new(db.get_islands()?)),
+        }
+    }
+
+    pub fn get_changed_towns(&self) -> anyhow::Result<Arc<Vec<Town>>> {
+        match self {
+            Model::Uninitialized => Ok(Arc::new(Vec::new())),
+            Model::Loaded { db, .. } => Ok(Arc::new(db.get_changed_towns()?)),
+        }
+    }
+
+    /// The entries of a pasted list that exist in the loaded data, and the ones that don't
+    pub fn resolve_list(
+        &self,
+        entries: &[ListEntry],
+    ) -> anyhow::Result<(Vec<ListEntry>, Vec<ListEntry>)> {
+        match self {
+            Model::Uninitialized => Ok((Vec::new(), entries.to_vec())),
+            Model::Loaded { db, .. } => db.resolve_list(entries),
+        }
+    }
+
+    /// Compare against the snapshot at `path` from now on. All cached results are stale afterwards.
+    pub fn set_baseline(&mut self, path: Option<&Path>) -> anyhow::Result<()> {
+        match self {
+            Model::Uninitialized => Ok(()),
+            Model::Loaded {
+                db,
+                cache_strings,
+                cache_towns,
+                cache_groups,
+                ..
+            } => {
+                cache_strings.clear();
+                cache_towns.clear();
+                cache_groups.clear();
+                db.set_baseline(path)
+            }
+        }
+    }
+
+    /// Recompute how long each player has been inactive. All cached results are stale afterwards.
+    /// Returns false if no data is loaded yet, so there was nothing to recompute.
+    pub fn compute_activity(&mut self, lookback: time::Duration) -> anyhow::Result<bool> {
+        match self {
+            Model::Uninitialized => Ok(false),
+            Model::Loaded {
+                db,
+                cache_strings,
+                cache_towns,
+                cache_groups,
+                ..
+            } => {
+                cache_strings.clear();
+                cache_towns.clear();
+                cache_groups.clear();
+                db.compute_activity(lookback)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// The regions controlled by each alliance. `radius` is how far the influence of a town
+    /// reaches, in world units.
+    pub fn get_territory(&self, radius: u8) -> anyhow::Result<Arc<Territory>> {
+        match self {
+            Model::Uninitialized => Ok(Arc::new(Territory::default())),
+            Model::Loaded { db, .. } => Ok(Arc::new(Territory::new(
+                &db.get_all_towns()?,
+                f32::from(radius),
+            ))),
+        }
+    }
+
     pub fn get_all_towns(&self) -> anyhow::Result<Arc<Vec<Town>>> {
         match self {
             Model::Uninitialized => Ok(Arc::new(Vec::new())),
@@ -0,0 +1,94 @@
+// Only SQL in here, so the benchmark in benches/ can include this file as well.
+
+/// Towns joined with their island, their position on the island, their player and the player's
+/// alliance. This is done once when the snapshot is created, so the queries only have to join the
+/// small tables that can change while the snapshot is loaded, all of them by their primary key.
+///
+/// Columns of the towns table keep their name, columns of the joined tables are prefixed with the
+/// name of that table. `ConstraintType::column` relies on this naming scheme.
+pub const CREATE_TABLE: &str = "CREATE TABLE towns_joined(
+    town_id INTEGER UNIQUE PRIMARY KEY,
+    player_id INTEGER,
+    name TEXT,
+    island_x INTEGER,
+    island_y INTEGER,
+    slot_number INTEGER,
+    points INTEGER,
+    offset_x INTEGER,
+    offset_y INTEGER,
+    player_name TEXT,
+    player_points INTEGER,
+    player_rank INTEGER,
+    player_towns INTEGER,
+    alliance_id INTEGER,
+    alliance_name TEXT,
+    alliance_points INTEGER,
+    alliance_towns INTEGER,
+    alliance_members INTEGER,
+    alliance_rank INTEGER,
+    island_id INTEGER,
+    island_type INTEGER,
+    island_towns INTEGER,
+    island_ressource_plus TEXT,
+    island_ressource_minus TEXT)";
+
+/// Towns on islands we know nothing about, or on slots the island type does not have, can't be
+/// drawn. They are left out here instead of being filtered in every single query.
+pub const FILL_TABLE: &str = "INSERT INTO towns_joined
+    SELECT
+        towns.town_id,
+        towns.player_id,
+        towns.name,
+        towns.island_x,
+        towns.island_y,
+        towns.slot_number,
+        towns.points,
+        offsets.offset_x,
+        offsets.offset_y,
+        players.name,
+        players.points,
+        players.rank,
+        players.towns,
+        players.alliance_id,
+        alliances.name,
+        alliances.points,
+        alliances.towns,
+        alliances.members,
+        alliances.rank,
+        islands.island_id,
+        islands.type,
+        islands.towns,
+        islands.ressource_plus,
+        islands.ressource_minus
+    FROM towns
+    JOIN islands ON (towns.island_x = islands.x AND towns.island_y = islands.y)
+    JOIN offsets ON (islands.type = offsets.type AND towns.slot_number = offsets.slot_number)
+    LEFT JOIN players ON (towns.player_id = players.player_id)
+    LEFT JOIN alliances ON (players.alliance_id = alliances.alliance_id)";
+
+/// The attributes that are most often compared for equality: names from the drop down lists and
+/// the ids used to join the derived tables.
+pub const CREATE_INDICES: [&str; 5] = [
+    "CREATE INDEX towns_joined_player_id ON towns_joined (player_id)",
+    "CREATE INDEX towns_joined_player_name ON towns_joined (player_name)",
+    "CREATE INDEX towns_joined_alliance_id ON towns_joined (alliance_id)",
+    "CREATE INDEX towns_joined_alliance_name ON towns_joined (alliance_name)",
+    "CREATE INDEX towns_joined_island ON towns_joined (island_x, island_y)",
+];
+
+/// The columns `Town::from` expects, in this order
+pub const TOWN_SELECTION: &str = "towns.town_id, towns.player_id, towns.name, \
+    towns.island_x, towns.island_y, towns.slot_number, towns.points, \
+    towns.offset_x, towns.offset_y, towns.player_name, towns.alliance_name, \
+    player_activity.hours_inactive, player_activity.last_growth";
+
+/// The pre-joined table is called `towns` in every query, so `towns.town_id`, `towns.player_id` etc.
+/// mean the same thing they mean in the raw towns table.
+pub const TOWN_TABLES: &str = "from \n\
+    towns_joined AS towns \n\
+    LEFT JOIN town_stats ON (towns.town_id = town_stats.town_id) \n\
+    LEFT JOIN player_stats ON (towns.player_id = player_stats.player_id) \n\
+    LEFT JOIN alliance_stats ON (towns.alliance_id = alliance_stats.alliance_id) \n\
+    LEFT JOIN town_changes ON (towns.town_id = town_changes.town_id) \n\
+    LEFT JOIN player_changes ON (towns.player_id = player_changes.player_id) \n\
+    LEFT JOIN player_activity ON (towns.player_id = player_activity.player_id)";
@@ -1,17 +1,22 @@
 use anyhow::Context;
 use eframe::epaint::ahash::HashMap;
 
+use crate::bbcode::ListEntry;
 use crate::emptyconstraint::EmptyConstraint;
 use crate::emptyselection::EmptyTownSelection;
 use crate::message::{MessageToModel, MessageToServer, MessageToView};
 use crate::model::database::Database;
 use crate::model::Model;
 use crate::storage;
-use crate::view::preferences::CacheSize;
+use crate::view::preferences::{CacheSize, InactivityWindow};
+use crate::view::UNICODE_FONT;
 use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
 
+/// Suggested number of towns for rank constraints that don't have a count yet.
+const DEFAULT_RANK_COUNT: usize = 10;
+
 /// Given a Result<MessageToView>, send it to the View if it is ok. If the sending
 /// fails, output to stderr with the message given in `error_channel`. If the given
 /// message result is error, simply output it to stderr.
@@ -40,9 +45,22 @@ fn send_to_view(
     }
 }
 
+/// Like `send_to_view`, but a failed query only affects the selection it was made for. The rest of
+/// the data is still fine, so there is no reason to tell the view that the whole backend crashed.
+fn send_to_view_for_selection(
+    tx: &mpsc::Sender<MessageToView>,
+    selection: &EmptyTownSelection,
+    msg_opt: anyhow::Result<MessageToView>,
+    error_channel: String,
+) {
+    let msg = msg_opt.unwrap_or_else(|err| MessageToView::SelectionFailed(selection.clone(), err));
+    send_to_view(tx, Ok(msg), error_channel);
+}
+
 pub struct Presenter {
     model: Model,
     max_cache_size: CacheSize,
+    inactivity_window: InactivityWindow,
     channel_tx: mpsc::Sender<MessageToView>,
     channel_rx: mpsc::Receiver<MessageToModel>,
     telemetry_tx: mpsc::Sender<MessageToServer>,
@@ -57,6 +75,7 @@ impl Presenter {
         Self {
             model: Model::Uninitialized,
             max_cache_size: CacheSize::Normal,
+            inactivity_window: InactivityWindow::default(),
             channel_tx: tx,
             channel_rx: rx,
             telemetry_tx,
@@ -66,31 +85,43 @@ impl Presenter {
     /// Return all possible selection names that can be used in the `DropDownValues` for the
     /// Constraint. Returns None if the Constraint is not a IN/NOT IN type of constraint. In which
     /// case it is up to the caller to determine which drop down values are appropriate.
+    /// Rank constraints get their current count, alone and followed by each selection name.
     fn possible_ddv_selections_or(
         constraint: &EmptyConstraint,
         selection: &EmptyTownSelection,
         all_selections: &[EmptyTownSelection],
     ) -> Option<Arc<Vec<String>>> {
+        let possible_names = || {
+            all_selections
+                .iter()
+                .map(|s| s.name.clone())
+                .filter(|name| name != &selection.name)
+                .filter(|name| {
+                    let mut test_selection = selection.clone();
+                    test_selection.constraints.push(EmptyConstraint {
+                        constraint_type: crate::constraint::ConstraintType::PlayerName,
+                        comparator: crate::constraint::Comparator::InSelection,
+                        value: name.clone(),
+                    });
+                    !test_selection.contains_circular_reference(all_selections)
+                })
+        };
+
+        if constraint.comparator.is_rank() {
+            let count = match constraint.rank_limit().0 {
+                0 => DEFAULT_RANK_COUNT,
+                count => count,
+            };
+            return Some(Arc::new(
+                std::iter::once(format!("{count}"))
+                    .chain(possible_names().map(|name| format!("{count} {name}")))
+                    .collect(),
+            ));
+        }
+
         constraint
             .referenced_selection()
-            .map(|_referenced_selection| {
-                Arc::new(
-                    all_selections
-                        .iter()
-                        .map(|s| s.name.clone())
-                        .filter(|name| name != &selection.name)
-                        .filter(|name| {
-                            let mut test_selection = selection.clone();
-                            test_selection.constraints.push(EmptyConstraint {
-                                constraint_type: crate::constraint::ConstraintType::PlayerName,
-                                comparator: crate::constraint::Comparator::InSelection,
-                                value: name.clone(),
-                            });
-                            !test_selection.contains_circular_reference(all_selections)
-                        })
-                        .collect(),
-                )
-            })
+            .map(|_referenced_selection| Arc::new(possible_names().collect()))
     }
 
     #[allow(clippy::too_many_lines)] // processing all variants of incoming messages simply needs a lot of lines
@@ -104,6 +135,20 @@ impl Presenter {
                 MessageToModel::MaxCacheSize(x) => {
                     self.max_cache_size = x;
                 }
+                MessageToModel::InactivityWindow(x) => {
+                    self.inactivity_window = x;
+                    let result = self.model.compute_activity(self.inactivity_window.value());
+                    // before a server is loaded there is nothing to refresh, the activity is
+                    // computed when the data is loaded
+                    if !matches!(result, Ok(false)) {
+                        let msg = result.map(|_| MessageToView::GotActivity);
+                        send_to_view(
+                            &self.channel_tx,
+                            msg,
+                            String::from("Failed to send message 'got activity'"),
+                        );
+                    }
+                }
                 MessageToModel::DiscoverSavedDatabases => {
                     let thread_tx = self.channel_tx.clone();
                     let handle = thread::spawn(move || {
@@ -116,6 +161,33 @@ impl Presenter {
                     });
                     spawned_threads.push(handle);
                 }
+                MessageToModel::ExportImage(scene, format, path, ctx) => {
+                    // drawing a large image takes a while, the ui has to stay responsive
+                    let thread_tx = self.channel_tx.clone();
+                    let handle = thread::spawn(move || {
+                        let result = scene
+                            .render(format, UNICODE_FONT, |share| {
+                                send_to_view(
+                                    &thread_tx,
+                                    Ok(MessageToView::ImageExportProgress(share)),
+                                    String::from("Failed to send the export progress to the view"),
+                                );
+                                ctx.request_repaint();
+                            })
+                            .and_then(|bytes| {
+                                std::fs::write(&path, bytes).with_context(|| {
+                                    format!("Failed to write the map to {}", path.display())
+                                })
+                            });
+                        send_to_view(
+                            &thread_tx,
+                            Ok(MessageToView::ImageExported(result.map(|()| path))),
+                            String::from("Failed to send message 'image exported'"),
+                        );
+                        ctx.request_repaint();
+                    });
+                    spawned_threads.push(handle);
+                }
                 MessageToModel::LoadDataFromFile(path, ctx) => {
                     let db_result = Database::load_from_file(&path);
                     match db_result {
@@ -125,7 +197,14 @@ impl Presenter {
                                 ctx,
                                 cache_strings: HashMap::default(),
                                 cache_towns: HashMap::default(),
+                                cache_groups: HashMap::default(),
                             };
+                            // not being able to tell who is inactive should not stop us from showing the map
+                            if let Err(err) =
+                                self.model.compute_activity(self.inactivity_window.value())
+                            {
+                                eprintln!("Failed to compute player activity: {err:?}");
+                            }
                             send_to_view(
                                 &self.channel_tx,
                                 Ok(MessageToView::GotServer),
@@ -163,7 +242,14 @@ impl Presenter {
                                 ctx,
                                 cache_strings: HashMap::default(),
                                 cache_towns: HashMap::default(),
+                                cache_groups: HashMap::default(),
                             };
+                            // not being able to tell who is inactive should not stop us from showing the map
+                            if let Err(err) =
+                                self.model.compute_activity(self.inactivity_window.value())
+                            {
+                                eprintln!("Failed to compute player activity: {err:?}");
+                            }
                             send_to_view(
                                 &self.channel_tx,
                                 Ok(MessageToView::GotServer),
@@ -203,6 +289,74 @@ impl Presenter {
                         String::from("Failed to send ghost town list to view"),
                     );
                 }
+                MessageToModel::FetchChanges => {
+                    let towns = self.model.get_changed_towns();
+                    let msg = towns.map(MessageToView::ChangedTowns);
+                    send_to_view(
+                        &self.channel_tx,
+                        msg,
+                        String::from("Failed to send changed town list to view"),
+                    );
+                }
+                MessageToModel::FetchFreeSlots(filter) => {
+                    let slots = self.model.get_free_slots(&filter);
+                    let msg = slots.map(MessageToView::FreeSlots);
+                    send_to_view(
+                        &self.channel_tx,
+                        msg,
+                        String::from("Failed to send free slot list to view"),
+                    );
+                }
+                MessageToModel::FetchIslands => {
+                    let islands = self.model.get_islands();
+                    let msg = islands.map(MessageToView::Islands);
+                    send_to_view(
+                        &self.channel_tx,
+                        msg,
+                        String::from("Failed to send island list to view"),
+                    );
+                }
+                MessageToModel::FetchTerritory(radius) => {
+                    let territory = self.model.get_territory(radius);
+                    let msg =
+                        territory.map(|territory| MessageToView::Territory(radius, territory));
+                    send_to_view(
+                        &self.channel_tx,
+                        msg,
+                        String::from("Failed to send territory to view"),
+                    );
+                }
+                MessageToModel::FetchTownDetails(town_id) => {
+                    // a failure is shown in the panel of the town
+                    let details = self.model.get_town_details(town_id);
+                    send_to_view(
+                        &self.channel_tx,
+                        Ok(MessageToView::TownDetails(town_id, details)),
+                        String::from("Failed to send town details to view"),
+                    );
+                }
+                MessageToModel::SetBaseline(path) => {
+                    let result = self.model.set_baseline(path.as_deref());
+                    let msg = result.map(|()| MessageToView::GotBaseline);
+                    send_to_view(
+                        &self.channel_tx,
+                        msg,
+                        String::from("Failed to send message 'got baseline'"),
+                    );
+                }
+                MessageToModel::ImportList(name, entries) => {
+                    let result = self.model.resolve_list(&entries);
+                    let msg = result.map(|(found, missing)| {
+                        let selection =
+                            (!found.is_empty()).then(|| ListEntry::to_selection(name, &found));
+                        MessageToView::ImportedList(selection, missing)
+                    });
+                    send_to_view(
+                        &self.channel_tx,
+                        msg,
+                        String::from("Failed to send imported list to view"),
+                    );
+                }
                 MessageToModel::FetchTowns(selection, constraints_edited, all_selections) => {
                     // a list of filled constraints that are not being edited. For each one, filter the ddv list by all _other_ filled, unedited constratins
                     let constraints_filled_not_edited: Vec<EmptyConstraint> = selection
@@ -247,8 +401,9 @@ impl Presenter {
                         let msg = possible_ddv.map(|t| {
                             MessageToView::ValueListForConstraint(c.clone(), selection.clone(), t)
                         });
-                        send_to_view(
+                        send_to_view_for_selection(
                             &self.channel_tx,
+                            &selection,
                             msg,
                             String::from("Failed to send town list for currently edited drop down"),
                         );
@@ -262,12 +417,79 @@ impl Presenter {
                     );
                     let msg =
                         towns.map(|t| MessageToView::TownListForSelection(selection.clone(), t));
-                    send_to_view(
+                    send_to_view_for_selection(
                         &self.channel_tx,
+                        &selection,
                         msg,
                         String::from("Failed to send town list to view"),
                     );
 
+                    // Summary of the towns of this selection
+                    let stats = self.model.get_stats_for_constraints(
+                        &selection,
+                        &constraints_filled_all,
+                        &all_selections,
+                    );
+                    let msg = stats.map(|s| MessageToView::StatsForSelection(selection.clone(), s));
+                    send_to_view_for_selection(
+                        &self.channel_tx,
+                        &selection,
+                        msg,
+                        String::from("Failed to send selection statistics to view"),
+                    );
+
+                    // Values the towns of this selection are coloured by
+                    if selection.gradient.is_some() {
+                        let values = self.model.get_gradient_values_for_constraints(
+                            &selection,
+                            &constraints_filled_all,
+                            &all_selections,
+                        );
+                        let msg = values.map(|v| {
+                            MessageToView::GradientValuesForSelection(selection.clone(), v)
+                        });
+                        send_to_view_for_selection(
+                            &self.channel_tx,
+                            &selection,
+                            msg,
+                            String::from("Failed to send gradient values to view"),
+                        );
+                    }
+
+                    // Density of this selection, drawn below its towns
+                    if selection.heatmap.is_some() {
+                        let heatmap = self.model.get_heatmap_for_constraints(
+                            &selection,
+                            &constraints_filled_all,
+                            &all_selections,
+                        );
+                        let msg = heatmap
+                            .map(|h| MessageToView::HeatmapForSelection(selection.clone(), h));
+                        send_to_view_for_selection(
+                            &self.channel_tx,
+                            &selection,
+                            msg,
+                            String::from("Failed to send heatmap to view"),
+                        );
+                    }
+
+                    // Players or alliances of this selection
+                    if selection.grouping.group_by_sql().is_some() {
+                        let groups = self.model.get_groups_for_constraints(
+                            &selection,
+                            &constraints_filled_all,
+                            &all_selections,
+                        );
+                        let msg =
+                            groups.map(|g| MessageToView::GroupsForSelection(selection.clone(), g));
+                        send_to_view_for_selection(
+                            &self.channel_tx,
+                            &selection,
+                            msg,
+                            String::from("Failed to send group list to view"),
+                        );
+                    }
+
                     // drop down values for the empty constraints
                     if !constraints_empty.is_empty() {
                         for c in constraints_empty {
@@ -289,8 +511,9 @@ impl Presenter {
                                     t,
                                 )
                             });
-                            send_to_view(
+                            send_to_view_for_selection(
                                 &self.channel_tx,
+                                &selection,
                                 msg,
                                 String::from("Failed to send town list to view"),
                             );
@@ -312,8 +535,9 @@ impl Presenter {
                         let msg = possible_ddv.map(|t| {
                             MessageToView::ValueListForConstraint(c, selection.clone(), t)
                         });
-                        send_to_view(
+                        send_to_view_for_selection(
                             &self.channel_tx,
+                            &selection,
                             msg,
                             String::from("Failed to send town list to view"),
                         );
@@ -346,8 +570,9 @@ impl Presenter {
                                     t,
                                 )
                             });
-                            send_to_view(
+                            send_to_view_for_selection(
                                 &self.channel_tx,
+                                &selection,
                                 msg,
                                 String::from("Failed to send town list to view"),
                             );
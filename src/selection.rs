@@ -3,14 +3,22 @@ use std::collections::HashSet;
 use std::default::Default;
 use std::sync::{mpsc, Arc};
 
+use anyhow::Context;
+use arboard::Clipboard;
 use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
 
-use crate::constraint::Constraint;
+use crate::bbcode::BBCodeTemplate;
+use crate::constraint::{Constraint, ConstraintType};
 use crate::emptyconstraint::EmptyConstraint;
 use crate::emptyselection::EmptyTownSelection;
+use crate::gradient::{Gradient, GradientValues, Scale};
+use crate::heatmap::{Heatmap, HeatmapSettings, Weighting};
+use crate::label::LabelStyle;
 use crate::message::MessageToModel;
-use crate::town::Town;
-use crate::view::{Change, Refresh};
+use crate::town::{parse_island_coordinates, SelectionStats, Town, TownGroup, TownShare};
+use crate::view::{groups::GroupSort, table::TownSort, Change, Refresh};
 
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
@@ -47,6 +55,45 @@ impl AndOr {
     }
 }
 
+/// How the towns of a selection are listed in addition to being drawn on the map
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Default, EnumIter)]
+pub enum Grouping {
+    #[default]
+    Towns,
+    Players,
+    Alliances,
+}
+
+impl ToString for Grouping {
+    fn to_string(&self) -> String {
+        match self {
+            Grouping::Towns => t!("selection.grouping.towns"),
+            Grouping::Players => t!("selection.grouping.players"),
+            Grouping::Alliances => t!("selection.grouping.alliances"),
+        }
+    }
+}
+
+impl Grouping {
+    /// The columns by which the towns are grouped, None if they are not grouped at all
+    pub fn group_by_sql(self) -> Option<&'static str> {
+        match self {
+            Grouping::Towns => None,
+            Grouping::Players => Some("towns.player_id"),
+            Grouping::Alliances => Some("towns.alliance_id"),
+        }
+    }
+
+    /// The name of a group
+    pub fn name_sql(self) -> &'static str {
+        match self {
+            Grouping::Towns => "towns.name",
+            Grouping::Players => "towns.player_name",
+            Grouping::Alliances => "towns.alliance_name",
+        }
+    }
+}
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(from = "EmptyTownSelection", into = "EmptyTownSelection")]
@@ -57,7 +104,26 @@ pub struct TownSelection {
     pub constraints: Vec<Constraint>,
     pub constraint_join_mode: AndOr,
     pub color: egui::Color32,
+    pub grouping: Grouping,
+    pub gradient: Option<Gradient>,
+    pub gradient_values: Arc<GradientValues>,
+    pub label: LabelStyle,
+    pub heatmap: Option<HeatmapSettings>,
+    pub heatmap_values: Arc<Heatmap>,
     pub towns: Arc<Vec<Town>>,
+    pub groups: Arc<Vec<TownGroup>>,
+    pub stats: Arc<SelectionStats>,
+    pub group_sort: GroupSort,
+    pub show_table: bool,
+    /// island coordinates the `BBCode` export measures distances from
+    pub bbcode_origin: String,
+    pub town_sort: TownSort,
+    /// Why the last export of the table failed, if it did
+    pub table_error: Option<String>,
+    /// Why the last copy of the `BBCode` failed, if it did
+    pub bbcode_error: Option<String>,
+    /// Why the last refresh of this selection failed, if it did
+    pub error: Option<String>,
 }
 
 // required for serde
@@ -128,6 +194,10 @@ impl TownSelection {
                 .collect(),
             constraint_join_mode: self.constraint_join_mode,
             color: self.color, // implements copy
+            grouping: self.grouping,
+            gradient: self.gradient,
+            label: self.label,
+            heatmap: self.heatmap,
         }
     }
 
@@ -168,14 +238,15 @@ impl TownSelection {
         all_selections: &[EmptyTownSelection],
     ) {
         // Check if there is a cycle. If so, do not send to the backend
-        // TODO inform the user of this!
         let referenced_selections = self
             .partial_clone()
             .all_referenced_selections(all_selections);
         if let Err(err) = referenced_selections {
             eprintln!("abort refresh: {err}");
+            self.error = Some(format!("{err:#}"));
             return;
         }
+        self.error = None;
 
         // this check introduces a bug! If this check is commented in all
         // dependents of this selection spam the backend with update requests.
@@ -207,6 +278,202 @@ impl TownSelection {
         // }
     }
 
+    /// Colour the towns by a numeric attribute instead of a single colour. Returns true if the
+    /// gradient was changed in a way that requires new values from the backend.
+    fn ui_gradient(&mut self, ui: &mut egui::Ui, selection_index: usize) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            let mut enabled = self.gradient.is_some();
+            if ui
+                .checkbox(&mut enabled, t!("selection.gradient.label"))
+                .changed()
+            {
+                self.gradient = enabled.then(Gradient::default);
+                changed = true;
+            }
+            let Some(gradient) = &mut self.gradient else {
+                return;
+            };
+
+            egui::ComboBox::from_id_source(format!("ComboxBox {selection_index} Gradient"))
+                .selected_text(gradient.attribute.to_string())
+                .show_ui(ui, |ui| {
+                    for value in ConstraintType::iter().filter(|value| !value.is_string()) {
+                        let text = value.to_string();
+                        if ui
+                            .selectable_value(&mut gradient.attribute, value, text)
+                            .clicked()
+                        {
+                            changed = true;
+                        }
+                    }
+                });
+            ui.color_edit_button_srgba(&mut gradient.low);
+            ui.color_edit_button_srgba(&mut gradient.high);
+            egui::ComboBox::from_id_source(format!("ComboxBox {selection_index} Gradient Scale"))
+                .selected_text(gradient.scale.to_string())
+                .show_ui(ui, |ui| {
+                    for value in Scale::iter() {
+                        let text = value.to_string();
+                        ui.selectable_value(&mut gradient.scale, value, text);
+                    }
+                });
+        });
+        changed
+    }
+
+    /// Show the density of the towns below them. Returns true if the heatmap was changed in a way
+    /// that requires a new one from the backend.
+    fn ui_heatmap(&mut self, ui: &mut egui::Ui, selection_index: usize) -> bool {
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            let mut enabled = self.heatmap.is_some();
+            if ui
+                .checkbox(&mut enabled, t!("selection.heatmap.label"))
+                .changed()
+            {
+                self.heatmap = enabled.then(HeatmapSettings::default);
+                changed = true;
+            }
+            let Some(heatmap) = &mut self.heatmap else {
+                return;
+            };
+
+            egui::ComboBox::from_id_source(format!("ComboxBox {selection_index} Heatmap"))
+                .selected_text(heatmap.weighting.to_string())
+                .show_ui(ui, |ui| {
+                    for value in Weighting::iter() {
+                        let text = value.to_string();
+                        if ui
+                            .selectable_value(&mut heatmap.weighting, value, text)
+                            .clicked()
+                        {
+                            changed = true;
+                        }
+                    }
+                });
+            ui.label(t!("selection.heatmap.radius"));
+            let response = ui.add(
+                egui::DragValue::new(&mut heatmap.radius)
+                    .clamp_range(2..=100)
+                    .speed(0.2),
+            );
+            // don't compute a new heatmap for every step while the value is dragged
+            if response.drag_released() || (response.changed() && !response.dragged()) {
+                changed = true;
+            }
+        });
+        changed
+    }
+
+    /// Copy the towns or groups of this selection to the clipboard, formatted for the in-game forum
+    fn ui_bbcode_menu(&mut self, ui: &mut egui::Ui) {
+        ui.menu_button(t!("selection.bbcode.button"), |ui| {
+            ui.horizontal(|ui| {
+                ui.label(t!("selection.bbcode.origin"));
+                ui.text_edit_singleline(&mut self.bbcode_origin);
+            });
+            let origin = parse_island_coordinates(&self.bbcode_origin);
+            for template in BBCodeTemplate::iter() {
+                let enabled = (!template.needs_origin() || origin.is_some())
+                    && (!template.needs_groups() || self.grouping != Grouping::Towns);
+                if ui
+                    .add_enabled(enabled, egui::Button::new(template.to_string()))
+                    .clicked()
+                {
+                    let text = template.export(&self.towns, self.grouping, &self.groups, origin);
+                    let result = Clipboard::new()
+                        .context(t!("selection.bbcode.no_clipboard"))
+                        .and_then(|mut clipboard| {
+                            clipboard
+                                .set_text(text)
+                                .context(t!("selection.bbcode.clipboard_failed"))
+                        });
+                    self.bbcode_error = result.err().map(|err| format!("{err:#}"));
+                    ui.close_menu();
+                }
+            }
+        });
+    }
+
+    /// Number of towns, players and alliances of this selection, their points and where they are
+    fn ui_stats(&self, ui: &mut egui::Ui, selection_index: usize) {
+        let stats = &self.stats;
+        egui::CollapsingHeader::new(t!("selection.stats.header"))
+            .id_source(format!("Stats {selection_index}"))
+            .show(ui, |ui| {
+                egui::Grid::new(format!("Stats Grid {selection_index}")).show(ui, |ui| {
+                    for (label, value) in [
+                        (t!("selection.stats.towns"), stats.towns.to_string()),
+                        (t!("selection.stats.players"), stats.players.to_string()),
+                        (t!("selection.stats.alliances"), stats.alliances.to_string()),
+                        (t!("selection.stats.points"), stats.points.to_string()),
+                        (
+                            t!("selection.stats.average_points"),
+                            stats.average_points().to_string(),
+                        ),
+                    ] {
+                        ui.label(label);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                });
+
+                egui::CollapsingHeader::new(t!("selection.stats.by_alliance"))
+                    .id_source(format!("Stats by Alliance {selection_index}"))
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .id_source(format!("Stats by Alliance Scroll {selection_index}"))
+                            .max_height(200.0)
+                            .show(ui, |ui| {
+                                Self::ui_share_grid(
+                                    ui,
+                                    format!("Stats by Alliance Grid {selection_index}"),
+                                    stats.by_alliance.iter().map(|(name, share)| {
+                                        (
+                                            name.clone()
+                                                .unwrap_or(t!("selection.grouping.no_name")),
+                                            *share,
+                                        )
+                                    }),
+                                );
+                            });
+                    });
+
+                egui::CollapsingHeader::new(t!("selection.stats.by_ocean"))
+                    .id_source(format!("Stats by Ocean {selection_index}"))
+                    .show(ui, |ui| {
+                        Self::ui_share_grid(
+                            ui,
+                            format!("Stats by Ocean Grid {selection_index}"),
+                            stats
+                                .by_ocean
+                                .iter()
+                                .map(|(ocean, share)| (format!("M{ocean}"), *share)),
+                        );
+                    });
+            });
+    }
+
+    fn ui_share_grid(
+        ui: &mut egui::Ui,
+        id: String,
+        rows: impl Iterator<Item = (String, TownShare)>,
+    ) {
+        egui::Grid::new(id).striped(true).show(ui, |ui| {
+            ui.label("");
+            ui.label(t!("selection.stats.towns"));
+            ui.label(t!("selection.stats.points"));
+            ui.end_row();
+            for (name, share) in rows {
+                ui.label(name);
+                ui.label(format!("{}", share.towns));
+                ui.label(format!("{}", share.points));
+                ui.end_row();
+            }
+        });
+    }
+
     #[allow(clippy::too_many_lines)]
     pub fn make_ui(
         &mut self,
@@ -265,6 +532,10 @@ impl TownSelection {
             if self.state == SelectionState::Loading {
                 ui.spinner();
             }
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().warn_fg_color, t!("selection.error"))
+                    .on_hover_text(error);
+            }
         })
         .body_unindented(|ui| {
             for (constraint_index, constraint) in self.constraints.iter_mut().enumerate() {
@@ -292,6 +563,49 @@ impl TownSelection {
                     constraint_change_action = change;
                 }
             }
+
+            ui.horizontal(|ui| {
+                ui.label(t!("selection.grouping.label"));
+                egui::ComboBox::from_id_source(format!("ComboxBox {selection_index} Grouping"))
+                    .selected_text(self.grouping.to_string())
+                    .show_ui(ui, |ui| {
+                        for value in Grouping::iter() {
+                            let text = value.to_string();
+                            if ui
+                                .selectable_value(&mut self.grouping, value, text)
+                                .clicked()
+                            {
+                                self.groups = Arc::new(Vec::new());
+                                refresh_action = Refresh::InSitu(HashSet::new());
+                            }
+                        }
+                    });
+                ui.toggle_value(&mut self.show_table, t!("selection.table.show"));
+                self.ui_bbcode_menu(ui);
+            });
+            if let Some(error) = &self.bbcode_error {
+                ui.colored_label(ui.visuals().error_fg_color, error);
+            }
+
+            if self.ui_gradient(ui, selection_index) {
+                self.gradient_values = Arc::new(GradientValues::default());
+                refresh_action = Refresh::InSitu(HashSet::new());
+            }
+
+            if self.ui_heatmap(ui, selection_index) {
+                self.heatmap_values = Arc::new(Heatmap::default());
+                refresh_action = Refresh::InSitu(HashSet::new());
+            }
+
+            ui.horizontal(|ui| {
+                ui.label(t!("selection.label"));
+                self.label
+                    .ui(ui, &format!("ComboBox {selection_index} Label"));
+            });
+
+            if !self.is_hidden() {
+                self.ui_stats(ui, selection_index);
+            }
         });
 
         if let Some(change) = constraint_change_action {
@@ -0,0 +1,122 @@
+use std::sync::{Arc, Weak};
+
+/// Side length of a grid cell in world units. A full world has about seven towns per cell.
+const CELL_SIZE: f32 = 10.0;
+/// The world is 1000 units wide and high. Positions outside of it land in the border cells.
+const CELLS_PER_SIDE: usize = 100;
+
+/// Something that has a position on the map
+pub trait Positioned {
+    fn position(&self) -> egui::Vec2;
+}
+
+/// A uniform grid over the positions of a list, so the entries in a rectangle or near a point
+/// can be found without going through the whole list
+#[derive(Debug, Clone, Default)]
+pub struct SpatialIndex {
+    /// index into the list and position of every entry, ordered by cell
+    entries: Vec<(usize, egui::Vec2)>,
+    /// where each cell starts in `entries`, followed by the total number of entries
+    cell_starts: Vec<usize>,
+}
+
+/// The cell of a coordinate along one axis
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn cell(coordinate: f32) -> usize {
+    ((coordinate / CELL_SIZE).max(0.0) as usize).min(CELLS_PER_SIDE - 1)
+}
+
+impl SpatialIndex {
+    pub fn new(positions: impl Iterator<Item = egui::Vec2>) -> Self {
+        let positions: Vec<egui::Vec2> = positions.collect();
+        let cell_of = |position: egui::Vec2| cell(position.y) * CELLS_PER_SIDE + cell(position.x);
+
+        // counting sort: count the entries per cell, then put every entry behind the ones of
+        // the previous cells
+        let mut cell_starts = vec![0; CELLS_PER_SIDE * CELLS_PER_SIDE + 1];
+        for position in &positions {
+            cell_starts[cell_of(*position) + 1] += 1;
+        }
+        for index in 1..cell_starts.len() {
+            cell_starts[index] += cell_starts[index - 1];
+        }
+        let mut next = cell_starts.clone();
+        let mut entries = vec![(0, egui::Vec2::ZERO); positions.len()];
+        for (index, position) in positions.into_iter().enumerate() {
+            let cell = cell_of(position);
+            entries[next[cell]] = (index, position);
+            next[cell] += 1;
+        }
+        Self {
+            entries,
+            cell_starts,
+        }
+    }
+
+    /// The entries of the cells that overlap the rectangle
+    fn cells_in(&self, rect: egui::Rect) -> impl Iterator<Item = &(usize, egui::Vec2)> {
+        let columns = cell(rect.left())..=cell(rect.right());
+        (cell(rect.top())..=cell(rect.bottom())).flat_map(move |row| {
+            let start = self.cell_starts[row * CELLS_PER_SIDE + columns.start()];
+            let end = self.cell_starts[row * CELLS_PER_SIDE + columns.end() + 1];
+            &self.entries[start..end]
+        })
+    }
+
+    /// Indices of the entries inside the rectangle, in no particular order
+    pub fn in_rect(&self, rect: egui::Rect) -> impl Iterator<Item = usize> + '_ {
+        self.cells_in(rect)
+            .filter(move |(_, position)| rect.contains(position.to_pos2()))
+            .map(|(index, _)| *index)
+    }
+
+    /// Index of and distance to the entry closest to the position, if one is closer than `reach`
+    pub fn nearest(&self, position: egui::Pos2, reach: f32) -> Option<(usize, f32)> {
+        let around = egui::Rect::from_center_size(position, egui::Vec2::splat(2.0 * reach));
+        self.cells_in(around)
+            .map(|(index, other)| (*index, position.distance(other.to_pos2())))
+            .filter(|(_, distance)| *distance < reach)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// The indices of the lists that are currently shown. The lists are shared and never changed
+/// in place, a change replaces the whole list. So an index stays valid for as long as its list
+/// lives, and is built again for the new list.
+pub struct IndexCache<T> {
+    entries: Vec<(Weak<Vec<T>>, Arc<SpatialIndex>)>,
+}
+
+impl<T> Default for IndexCache<T> {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<T> Clone for IndexCache<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<T: Positioned> IndexCache<T> {
+    pub fn get(&mut self, list: &Arc<Vec<T>>) -> Arc<SpatialIndex> {
+        // a list that was dropped can't be shown anymore
+        self.entries.retain(|(weak, _)| weak.strong_count() > 0);
+        if let Some((_, index)) = self
+            .entries
+            .iter()
+            .find(|(weak, _)| std::ptr::eq(weak.as_ptr(), Arc::as_ptr(list)))
+        {
+            return Arc::clone(index);
+        }
+        let index = Arc::new(SpatialIndex::new(list.iter().map(Positioned::position)));
+        self.entries
+            .push((Arc::downgrade(list), Arc::clone(&index)));
+        index
+    }
+}
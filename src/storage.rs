@@ -69,6 +69,13 @@ impl From<PathBuf> for SavedDB {
     }
 }
 
+impl SavedDB {
+    /// the id of the server this database was downloaded from, e.g. de99
+    pub fn server(&self) -> &str {
+        &self.server_str
+    }
+}
+
 impl Ord for SavedDB {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.path.cmp(&other.path)
@@ -104,6 +111,12 @@ impl Display for SavedDB {
     }
 }
 
+/// format a date the same way the dates of saved databases are shown
+pub fn format_date(date: OffsetDateTime) -> String {
+    date.format(&FORMAT_DISPLAY)
+        .unwrap_or_else(|_err| date.to_string())
+}
+
 /// returns a path to a not yet existing sqlite file. If the
 /// function returns `Some(path)`, the parent directory is
 /// guaranteed to exist.
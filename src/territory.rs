@@ -0,0 +1,239 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::selection::TownSelection;
+use crate::town::Town;
+
+/// The world is 1000 units wide and high
+const WORLD_SIZE: f32 = 1000.0;
+/// Side length of a cell of the territory grid, in world units
+const CELL_SIZE: f32 = 2.0;
+/// Marks cells that no alliance has any influence on
+const NO_OWNER: u16 = u16::MAX;
+
+/// The regions of the map each alliance controls. Every town spreads the influence of its
+/// alliance around it, weighted by its points, and each cell belongs to the alliance with the
+/// most influence on it.
+#[derive(Debug, Clone, Default)]
+pub struct Territory {
+    /// number of cells along each side
+    pub side: usize,
+    /// index into `alliances` of the owner of each cell, row by row from the top left
+    owners: Vec<u16>,
+    /// names of the alliances, sorted
+    pub alliances: Vec<String>,
+    /// borders between regions, in world coordinates
+    pub borders: Vec<[egui::Pos2; 2]>,
+}
+
+impl Territory {
+    /// `radius` is how far the influence of a town reaches, in world units
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(towns: &[Town], radius: f32) -> Self {
+        let mut towns_by_alliance: BTreeMap<&str, Vec<&Town>> = BTreeMap::new();
+        for town in towns {
+            if let Some(alliance) = &town.alliance_name {
+                towns_by_alliance.entry(alliance).or_default().push(town);
+            }
+        }
+
+        let radius = radius.max(1.0);
+        let side = (WORLD_SIZE / CELL_SIZE).ceil() as usize;
+        let reach = (radius / CELL_SIZE).ceil() as isize;
+        let last = side as isize - 1;
+        let mut owners = vec![NO_OWNER; side * side];
+        let mut strongest = vec![0.0_f32; side * side];
+        // the influence of one alliance at a time, so only two grids are needed
+        let mut influence = vec![0.0_f32; side * side];
+
+        for (alliance_index, alliance_towns) in towns_by_alliance.values().enumerate() {
+            let (mut top, mut left, mut bottom, mut right) = (last, last, 0, 0);
+            for town in alliance_towns {
+                let column = (town.x / CELL_SIZE).floor() as isize;
+                let row = (town.y / CELL_SIZE).floor() as isize;
+                let (rows, columns) = (
+                    (row - reach).max(0)..=(row + reach).min(last),
+                    (column - reach).max(0)..=(column + reach).min(last),
+                );
+                top = top.min(*rows.start());
+                bottom = bottom.max(*rows.end());
+                left = left.min(*columns.start());
+                right = right.max(*columns.end());
+                // every town counts, even one without points
+                let weight = f32::from(town.points).max(1.0);
+                for y in rows {
+                    for x in columns.clone() {
+                        let center = egui::vec2(x as f32 + 0.5, y as f32 + 0.5) * CELL_SIZE;
+                        let distance =
+                            (center - egui::vec2(town.x, town.y)).length_sq() / (radius * radius);
+                        if distance < 1.0 {
+                            influence[y as usize * side + x as usize] +=
+                                weight * (1.0 - distance).powi(2);
+                        }
+                    }
+                }
+            }
+            for y in top..=bottom {
+                for x in left..=right {
+                    let cell = y as usize * side + x as usize;
+                    if influence[cell] > strongest[cell] {
+                        strongest[cell] = influence[cell];
+                        owners[cell] = alliance_index as u16;
+                    }
+                    influence[cell] = 0.0;
+                }
+            }
+        }
+
+        let mut territory = Self {
+            side,
+            owners,
+            alliances: towns_by_alliance
+                .keys()
+                .map(|name| (*name).to_owned())
+                .collect(),
+            borders: Vec::new(),
+        };
+        territory.borders = territory.find_borders();
+        territory
+    }
+
+    fn owner(&self, x: usize, y: usize) -> u16 {
+        if x < self.side && y < self.side {
+            self.owners[y * self.side + x]
+        } else {
+            NO_OWNER
+        }
+    }
+
+    /// Lines between neighbouring cells with different owners. Neighbouring lines on the same
+    /// grid line are merged, so there are far fewer of them than cells.
+    #[allow(clippy::cast_precision_loss)]
+    fn find_borders(&self) -> Vec<[egui::Pos2; 2]> {
+        let mut borders = Vec::new();
+        let position = |index: usize| index as f32 * CELL_SIZE;
+        // `across` is the index of the grid line, `along` the cell next to it
+        for vertical in [true, false] {
+            for across in 0..=self.side {
+                let mut start = None;
+                for along in 0..=self.side {
+                    let differs = along < self.side && {
+                        let (before, after) = if vertical {
+                            (
+                                across
+                                    .checked_sub(1)
+                                    .map_or(NO_OWNER, |x| self.owner(x, along)),
+                                self.owner(across, along),
+                            )
+                        } else {
+                            (
+                                across
+                                    .checked_sub(1)
+                                    .map_or(NO_OWNER, |y| self.owner(along, y)),
+                                self.owner(along, across),
+                            )
+                        };
+                        before != after
+                    };
+                    match (differs, start) {
+                        (true, None) => start = Some(along),
+                        (false, Some(first)) => {
+                            let (a, b) = (position(first), position(along));
+                            let line = position(across);
+                            borders.push(if vertical {
+                                [egui::pos2(line, a), egui::pos2(line, b)]
+                            } else {
+                                [egui::pos2(a, line), egui::pos2(b, line)]
+                            });
+                            start = None;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        borders
+    }
+
+    /// Runs of cells with the same owner in a row, as a rectangle in world coordinates and the
+    /// index of the owning alliance. Fewer shapes than one per cell when exporting.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn regions(&self) -> Vec<(egui::Rect, usize)> {
+        let mut regions = Vec::new();
+        for y in 0..self.side {
+            let mut x = 0;
+            while x < self.side {
+                let owner = self.owner(x, y);
+                let start = x;
+                while x < self.side && self.owner(x, y) == owner {
+                    x += 1;
+                }
+                if owner != NO_OWNER {
+                    regions.push((
+                        egui::Rect::from_min_max(
+                            egui::pos2(start as f32 * CELL_SIZE, y as f32 * CELL_SIZE),
+                            egui::pos2(x as f32 * CELL_SIZE, (y + 1) as f32 * CELL_SIZE),
+                        ),
+                        usize::from(owner),
+                    ));
+                }
+            }
+        }
+        regions
+    }
+
+    /// An image with one pixel per cell in the colour of its owner
+    pub fn image(&self, colors: &[egui::Color32]) -> egui::ColorImage {
+        let pixels = self
+            .owners
+            .iter()
+            .map(|owner| {
+                colors
+                    .get(usize::from(*owner))
+                    .copied()
+                    .unwrap_or(egui::Color32::TRANSPARENT)
+            })
+            .collect();
+        egui::ColorImage {
+            size: [self.side, self.side],
+            pixels,
+        }
+    }
+
+    /// The colour of every alliance, with the given opacity from 0 to 255. An alliance takes the
+    /// colour of the first visible selection that contains one of its towns, the others get a
+    /// colour derived from their name, so it stays the same every time.
+    pub fn colors(&self, selections: &[TownSelection], opacity: u8) -> Vec<egui::Color32> {
+        let mut from_selections: HashMap<&str, egui::Color32> = HashMap::new();
+        for selection in selections.iter().filter(|s| !s.is_hidden()) {
+            for town in selection.towns.iter() {
+                if let Some(alliance) = &town.alliance_name {
+                    from_selections.entry(alliance).or_insert(selection.color);
+                }
+            }
+        }
+        self.alliances
+            .iter()
+            .map(|alliance| {
+                let color = from_selections
+                    .get(alliance.as_str())
+                    .copied()
+                    .unwrap_or_else(|| automatic_color(alliance));
+                let [r, g, b, _] = color.to_srgba_unmultiplied();
+                egui::Color32::from_rgba_unmultiplied(r, g, b, opacity)
+            })
+            .collect()
+    }
+}
+
+/// A bright colour with a hue picked by the name of the alliance. The checksum of the name stays
+/// the same across versions of Rust, unlike the standard hasher.
+fn automatic_color(alliance: &str) -> egui::Color32 {
+    #[allow(clippy::cast_precision_loss)]
+    let hue = (crc32fast::hash(alliance.as_bytes()) % 360) as f32 / 360.0;
+    egui::ecolor::Hsva::new(hue, 0.7, 0.9, 1.0).into()
+}
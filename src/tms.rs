@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+use std::io::{Read, Write};
+
+use anyhow::Context;
+use base64::Engine;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use serde::{Deserialize, Serialize};
+
+use crate::emptyselection::EmptyTownSelection;
+
+/// Version of the .tms format written by this version of the app. Increase it whenever the
+/// format changes in a way that older versions can't read.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Share codes start with this, so they can be told apart from YAML
+pub const SHARE_CODE_PREFIX: &str = "tms1:";
+
+/// A .tms file, a list of selections to share with allies or to keep on hand
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TmsFile {
+    pub version: u32,
+    /// The server the selections were made for, e.g. "de99"
+    #[serde(default)]
+    pub server: Option<String>,
+    pub selections: Vec<EmptyTownSelection>,
+}
+
+/// What happened when a single file or text was imported
+#[derive(Debug, Clone)]
+pub struct TmsReport {
+    /// file name, or where the text came from
+    pub source: String,
+    pub imported: usize,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+/// Turn a serde error into a message that says where in the text the problem is
+fn describe(err: &serde_yaml::Error) -> String {
+    match err.location() {
+        Some(location) => t!(
+            "menu.import.tms.location",
+            line = location.line(),
+            column = location.column(),
+            error = err
+        ),
+        None => format!("{err}"),
+    }
+}
+
+impl TmsFile {
+    pub fn new(server: &str, selections: Vec<EmptyTownSelection>) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            server: Some(server.to_string()),
+            selections,
+        }
+    }
+
+    pub fn to_yaml(&self) -> anyhow::Result<String> {
+        serde_yaml::to_string(self).context("Failed to convert the list of selections into YAML")
+    }
+
+    /// A single line that survives chat programs: the YAML of the file, compressed and base64url
+    /// encoded, followed by a checksum of the compressed bytes.
+    pub fn to_share_code(&self) -> anyhow::Result<String> {
+        let yaml = self.to_yaml()?;
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(yaml.as_bytes())
+            .context("Failed to compress the selections")?;
+        let compressed = encoder
+            .finish()
+            .context("Failed to compress the selections")?;
+        let checksum = crc32fast::hash(&compressed);
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(compressed);
+        Ok(format!("{SHARE_CODE_PREFIX}{payload}.{checksum:08x}"))
+    }
+
+    /// The YAML contained in a share code
+    fn decode_share_code(code: &str) -> anyhow::Result<String> {
+        // chat programs like to break long lines
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let code = code
+            .strip_prefix(SHARE_CODE_PREFIX)
+            .context(t!("menu.import.share_code.not_a_share_code"))?;
+        let (payload, checksum) = code
+            .rsplit_once('.')
+            .context(t!("menu.import.share_code.no_checksum"))?;
+        let checksum = u32::from_str_radix(checksum, 16)
+            .context(t!("menu.import.share_code.invalid_checksum"))?;
+        let compressed = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload)
+            .context(t!("menu.import.share_code.invalid_characters"))?;
+        if crc32fast::hash(&compressed) != checksum {
+            return Err(anyhow::anyhow!(t!(
+                "menu.import.share_code.checksum_mismatch"
+            )));
+        }
+        let mut yaml = String::new();
+        DeflateDecoder::new(compressed.as_slice())
+            .read_to_string(&mut yaml)
+            .context(t!("menu.import.share_code.decompress_failed"))?;
+        Ok(yaml)
+    }
+
+    /// Read the selections of a .tms file and collect everything that looks off as warnings.
+    /// Files without a header were written by older versions and contain either a list of
+    /// selections or a single selection. Share codes are unpacked first.
+    pub fn parse(
+        text: &str,
+        current_server: &str,
+        existing_names: &[String],
+    ) -> anyhow::Result<(Vec<EmptyTownSelection>, Vec<String>)> {
+        let mut warnings = Vec::new();
+
+        let decoded;
+        let text = if text.trim_start().starts_with(SHARE_CODE_PREFIX) {
+            decoded = Self::decode_share_code(text)?;
+            decoded.as_str()
+        } else {
+            text
+        };
+
+        let value: serde_yaml::Value = serde_yaml::from_str(text).map_err(|err| {
+            anyhow::anyhow!(t!("menu.import.tms.invalid_yaml", at = describe(&err)))
+        })?;
+        let selections = match &value {
+            serde_yaml::Value::Mapping(mapping) if mapping.contains_key("version") => {
+                let file: Self = serde_yaml::from_str(text).map_err(|err| {
+                    anyhow::anyhow!(t!("menu.import.tms.invalid_file", at = describe(&err)))
+                })?;
+                if file.version > FORMAT_VERSION {
+                    return Err(anyhow::anyhow!(t!(
+                        "menu.import.tms.newer_version",
+                        version = file.version,
+                        supported = FORMAT_VERSION
+                    )));
+                }
+                match file.server {
+                    Some(server) if !current_server.is_empty() && server != current_server => {
+                        warnings.push(t!(
+                            "menu.import.tms.other_server",
+                            server = server,
+                            current = current_server
+                        ));
+                    }
+                    _ => {}
+                }
+                file.selections
+            }
+            serde_yaml::Value::Sequence(_) => {
+                warnings.push(t!("menu.import.tms.no_header"));
+                serde_yaml::from_str(text).map_err(|err| {
+                    anyhow::anyhow!(t!("menu.import.tms.invalid_list", at = describe(&err)))
+                })?
+            }
+            serde_yaml::Value::Mapping(_) => {
+                warnings.push(t!("menu.import.tms.no_header"));
+                vec![serde_yaml::from_str(text).map_err(|err| {
+                    anyhow::anyhow!(t!("menu.import.tms.invalid_selection", at = describe(&err)))
+                })?]
+            }
+            _ => return Err(anyhow::anyhow!(t!("menu.import.tms.unexpected"))),
+        };
+
+        let mut names = HashSet::new();
+        for selection in &selections {
+            if !names.insert(selection.name.as_str()) {
+                return Err(anyhow::anyhow!(t!(
+                    "menu.import.tms.duplicate_name",
+                    name = selection.name
+                )));
+            }
+        }
+        for selection in &selections {
+            for name in selection.directly_referenced_selection_names() {
+                if !names.contains(name.as_str()) && !existing_names.contains(&name) {
+                    warnings.push(t!(
+                        "menu.import.tms.missing_reference",
+                        selection = selection.name,
+                        name = name
+                    ));
+                }
+            }
+        }
+
+        Ok((selections, warnings))
+    }
+}
+
+impl TmsReport {
+    /// Read a .tms text and describe how that went
+    pub fn read(
+        source: String,
+        text: &str,
+        current_server: &str,
+        existing_names: &[String],
+    ) -> (Vec<EmptyTownSelection>, Self) {
+        match TmsFile::parse(text, current_server, existing_names) {
+            Ok((selections, warnings)) => {
+                let report = Self {
+                    source,
+                    imported: selections.len(),
+                    errors: Vec::new(),
+                    warnings,
+                };
+                (selections, report)
+            }
+            Err(err) => {
+                let report = Self {
+                    source,
+                    imported: 0,
+                    errors: vec![format!("{err:#}")],
+                    warnings: Vec::new(),
+                };
+                (Vec::new(), report)
+            }
+        }
+    }
+
+    /// A file that could not even be read
+    pub fn failed(source: String, err: &anyhow::Error) -> Self {
+        Self {
+            source,
+            imported: 0,
+            errors: vec![format!("{err:#}")],
+            warnings: Vec::new(),
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.errors.is_empty() && self.warnings.is_empty()
+    }
+}
@@ -1,4 +1,7 @@
 use rusqlite::Row;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::spatial::Positioned;
 
 #[derive(Debug, Clone)]
 pub struct Town {
@@ -7,10 +10,14 @@ pub struct Town {
     pub player_name: Option<String>,
     pub alliance_name: Option<String>,
     pub name: String,
+    pub island_x: u16,
+    pub island_y: u16,
     pub x: f32,
     pub y: f32,
     pub slot_number: u8,
     pub points: u16,
+    pub player_hours_inactive: Option<i64>,
+    pub player_last_growth: Option<String>,
 }
 
 impl Town {
@@ -21,10 +28,152 @@ impl Town {
             player_name: row.get(9)?,
             alliance_name: row.get(10)?,
             name: row.get(2)?,
+            island_x: row.get(3)?,
+            island_y: row.get(4)?,
             x: row.get::<usize, f32>(3)? + row.get::<usize, f32>(7)? / 125.0,
             y: row.get::<usize, f32>(4)? + row.get::<usize, f32>(8)? / 125.0,
             slot_number: row.get(5)?,
             points: row.get(6)?,
+            player_hours_inactive: row.get(11)?,
+            player_last_growth: row.get(12)?,
         })
     }
+
+    /// The ocean the town lies in, as shown in game
+    #[allow(clippy::cast_possible_truncation)] // map coordinates are in 0..1000
+    pub fn ocean(&self) -> u8 {
+        (self.island_x / 100 * 10 + self.island_y / 100) as u8
+    }
+}
+
+impl Positioned for Town {
+    fn position(&self) -> egui::Vec2 {
+        egui::vec2(self.x, self.y)
+    }
+}
+
+/// A town with the points and ranks of its player and alliance, for the panel that opens when
+/// the town is clicked on the map
+#[derive(Debug, Clone)]
+pub struct TownDetails {
+    pub town: Town,
+    pub player_points: Option<u32>,
+    pub player_rank: Option<u32>,
+    pub player_towns: Option<u32>,
+    pub alliance_points: Option<u32>,
+    pub alliance_rank: Option<u32>,
+    pub alliance_members: Option<u32>,
+}
+
+impl TownDetails {
+    /// Expects the columns of `Town::from`, followed by the player and alliance columns
+    pub fn from(row: &Row) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            town: Town::from(row)?,
+            player_points: row.get(13)?,
+            player_rank: row.get(14)?,
+            player_towns: row.get(15)?,
+            alliance_points: row.get(16)?,
+            alliance_rank: row.get(17)?,
+            alliance_members: row.get(18)?,
+        })
+    }
+}
+
+/// The towns of a selection, grouped by player or by alliance
+#[derive(Debug, Clone)]
+pub struct TownGroup {
+    /// None for ghost towns or players without an alliance
+    pub name: Option<String>,
+    pub players: u32,
+    pub towns: u32,
+    pub points: u32,
+}
+
+impl TownGroup {
+    pub fn from(row: &Row) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            name: row.get(0)?,
+            players: row.get(1)?,
+            towns: row.get(2)?,
+            points: row.get(3)?,
+        })
+    }
+}
+
+/// Island coordinates as written in game, e.g. "475|512"
+pub fn parse_island_coordinates(text: &str) -> Option<(u16, u16)> {
+    let (x, y) = text.split_once('|')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// Number of towns and their points, for one line of a breakdown in `SelectionStats`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TownShare {
+    pub towns: u32,
+    pub points: u64,
+}
+
+/// Summary of the towns of a selection
+#[derive(Debug, Clone, Default)]
+pub struct SelectionStats {
+    pub towns: usize,
+    pub players: usize,
+    pub alliances: usize,
+    pub points: u64,
+    /// Sorted by points, biggest alliance first. None collects ghost towns and players without an alliance.
+    pub by_alliance: Vec<(Option<String>, TownShare)>,
+    /// Sorted by ocean number
+    pub by_ocean: Vec<(u8, TownShare)>,
+}
+
+impl SelectionStats {
+    pub fn from_towns(towns: &[Town]) -> Self {
+        let mut players = HashSet::new();
+        let mut by_alliance: HashMap<Option<&str>, TownShare> = HashMap::new();
+        let mut by_ocean: BTreeMap<u8, TownShare> = BTreeMap::new();
+        let mut points = 0;
+        for town in towns {
+            if let Some(player_id) = town.player_id {
+                players.insert(player_id);
+            }
+            points += u64::from(town.points);
+            for share in [
+                by_alliance
+                    .entry(town.alliance_name.as_deref())
+                    .or_default(),
+                by_ocean.entry(town.ocean()).or_default(),
+            ] {
+                share.towns += 1;
+                share.points += u64::from(town.points);
+            }
+        }
+
+        let mut by_alliance: Vec<(Option<String>, TownShare)> = by_alliance
+            .into_iter()
+            .map(|(name, share)| (name.map(str::to_string), share))
+            .collect();
+        by_alliance.sort_by(|a, b| b.1.points.cmp(&a.1.points).then_with(|| a.0.cmp(&b.0)));
+
+        Self {
+            towns: towns.len(),
+            players: players.len(),
+            alliances: by_alliance
+                .iter()
+                .filter(|(name, _)| name.is_some())
+                .count(),
+            points,
+            by_alliance,
+            by_ocean: by_ocean.into_iter().collect(),
+        }
+    }
+
+    /// Average points per town, 0 for an empty selection
+    pub fn average_points(&self) -> u64 {
+        if self.towns == 0 {
+            0
+        } else {
+            self.points / self.towns as u64
+        }
+    }
 }
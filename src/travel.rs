@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+/// A world unit is the distance between two island coordinates, which is this many pixels on
+/// the map of the game
+const PIXELS_PER_WORLD_UNIT: f32 = 128.0;
+/// A unit with speed 1 on a world with speed 1 needs this many seconds per pixel of the game map
+const SECONDS_PER_PIXEL: f32 = 50.0;
+/// The lighthouse makes all ships this much faster
+const LIGHTHOUSE_BONUS: f32 = 0.15;
+/// The meteorology research makes all land units this much faster
+const METEOROLOGY_BONUS: f32 = 0.1;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, EnumIter,
+)]
+pub enum Unit {
+    Swordsman,
+    Slinger,
+    Archer,
+    Hoplite,
+    Horseman,
+    Chariot,
+    Catapult,
+    TransportBoat,
+    Bireme,
+    LightShip,
+    FireShip,
+    FastTransport,
+    Trireme,
+    ColonyShip,
+}
+
+impl ToString for Unit {
+    fn to_string(&self) -> String {
+        match self {
+            Unit::Swordsman => t!("map.ruler.unit.swordsman"),
+            Unit::Slinger => t!("map.ruler.unit.slinger"),
+            Unit::Archer => t!("map.ruler.unit.archer"),
+            Unit::Hoplite => t!("map.ruler.unit.hoplite"),
+            Unit::Horseman => t!("map.ruler.unit.horseman"),
+            Unit::Chariot => t!("map.ruler.unit.chariot"),
+            Unit::Catapult => t!("map.ruler.unit.catapult"),
+            Unit::TransportBoat => t!("map.ruler.unit.transport_boat"),
+            Unit::Bireme => t!("map.ruler.unit.bireme"),
+            Unit::LightShip => t!("map.ruler.unit.light_ship"),
+            Unit::FireShip => t!("map.ruler.unit.fire_ship"),
+            Unit::FastTransport => t!("map.ruler.unit.fast_transport"),
+            Unit::Trireme => t!("map.ruler.unit.trireme"),
+            Unit::ColonyShip => t!("map.ruler.unit.colony_ship"),
+        }
+    }
+}
+
+impl Unit {
+    /// The speed of the unit in the game, without any modifiers
+    pub fn default_speed(self) -> u8 {
+        match self {
+            Unit::Swordsman | Unit::TransportBoat => 8,
+            Unit::Slinger => 14,
+            Unit::Archer => 12,
+            Unit::Hoplite => 6,
+            Unit::Horseman => 22,
+            Unit::Chariot => 18,
+            Unit::Catapult => 2,
+            Unit::Bireme | Unit::Trireme => 15,
+            Unit::LightShip | Unit::FastTransport => 13,
+            Unit::FireShip => 5,
+            Unit::ColonyShip => 3,
+        }
+    }
+
+    pub fn is_naval(self) -> bool {
+        matches!(
+            self,
+            Unit::TransportBoat
+                | Unit::Bireme
+                | Unit::LightShip
+                | Unit::FireShip
+                | Unit::FastTransport
+                | Unit::Trireme
+                | Unit::ColonyShip
+        )
+    }
+}
+
+/// What the travel times of the ruler are calculated with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TravelSettings {
+    pub world_speed: f32,
+    /// speed of every unit, a unit that is missing moves with its default speed
+    pub unit_speeds: BTreeMap<Unit, u8>,
+    pub lighthouse: bool,
+    pub meteorology: bool,
+}
+
+impl Default for TravelSettings {
+    fn default() -> Self {
+        Self {
+            world_speed: 1.0,
+            unit_speeds: Unit::iter()
+                .map(|unit| (unit, unit.default_speed()))
+                .collect(),
+            lighthouse: false,
+            meteorology: false,
+        }
+    }
+}
+
+impl TravelSettings {
+    pub fn speed(&self, unit: Unit) -> u8 {
+        self.unit_speeds
+            .get(&unit)
+            .copied()
+            .unwrap_or_else(|| unit.default_speed())
+    }
+
+    /// How long the unit needs for a distance in world units. None if the unit can't move.
+    pub fn travel_time(&self, unit: Unit, distance: f32) -> Option<Duration> {
+        let bonus = match (unit.is_naval(), self.lighthouse, self.meteorology) {
+            (true, true, _) => LIGHTHOUSE_BONUS,
+            (false, _, true) => METEOROLOGY_BONUS,
+            _ => 0.0,
+        };
+        let speed = f32::from(self.speed(unit)) * self.world_speed * (1.0 + bonus);
+        if speed <= 0.0 {
+            return None;
+        }
+        let seconds = distance * PIXELS_PER_WORLD_UNIT * SECONDS_PER_PIXEL / speed;
+        Some(Duration::from_secs_f32(seconds))
+    }
+}
+
+/// A travel time the way the game shows it, e.g. 1:05:09 or 27:00:00
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,244 @@
+use std::ops::RangeInclusive;
+
+use crate::constraint::{Comparator, ConstraintType};
+use crate::emptyconstraint::EmptyConstraint;
+use crate::emptyselection::EmptyTownSelection;
+use crate::selection::AndOr;
+use crate::spatial::SpatialIndex;
+use crate::town::Town;
+
+use super::View;
+
+/// Points of a lasso that are closer to the previous one than this many pixels are skipped
+pub const LASSO_STEP_PX: f32 = 4.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AreaShape {
+    /// shift and drag
+    Rectangle,
+    /// ctrl and drag
+    Lasso,
+}
+
+/// An area that is drawn on the map to pick the towns inside it
+#[derive(Debug, Clone)]
+pub struct AreaSelection {
+    pub shape: AreaShape,
+    /// in world coordinates. The two corners of a rectangle, or the path of a lasso.
+    pub points: Vec<egui::Vec2>,
+    /// false while the mouse button is still held down
+    pub finished: bool,
+    /// ids of the towns inside the area, once it is finished
+    pub town_ids: Vec<i32>,
+    /// number of towns on the islands inside the area, which the island constraints select
+    pub island_towns: usize,
+    /// name of the selection that is created from the towns
+    pub name: String,
+    /// name of the selection the area constraint is added to
+    pub target: String,
+}
+
+impl AreaSelection {
+    pub fn new(shape: AreaShape, start: egui::Vec2) -> Self {
+        Self {
+            shape,
+            points: vec![start],
+            finished: false,
+            town_ids: Vec::new(),
+            island_towns: 0,
+            name: t!("map.area.default_name"),
+            target: String::new(),
+        }
+    }
+
+    /// Follow the mouse while it is dragged. `min_step` is the lasso step in world units.
+    pub fn extend(&mut self, position: egui::Vec2, min_step: f32) {
+        match self.shape {
+            AreaShape::Rectangle => {
+                self.points.truncate(1);
+                self.points.push(position);
+            }
+            AreaShape::Lasso => {
+                let far_enough = self
+                    .points
+                    .last()
+                    .is_none_or(|last| (*last - position).length() >= min_step);
+                if far_enough {
+                    self.points.push(position);
+                }
+            }
+        }
+    }
+
+    /// Stop following the mouse and collect the towns inside the area
+    pub fn finish(&mut self, towns: &[Town], index: &SpatialIndex) {
+        self.finished = true;
+        self.town_ids = index
+            .in_rect(self.bounds())
+            .map(|index| &towns[index])
+            .filter(|town| self.contains(egui::vec2(town.x, town.y)))
+            .map(|town| town.id)
+            .collect();
+        // the index returns the towns in no particular order
+        self.town_ids.sort_unstable();
+        let (x, y) = self.island_ranges();
+        self.island_towns = towns
+            .iter()
+            .filter(|town| {
+                x.contains(&f32::from(town.island_x)) && y.contains(&f32::from(town.island_y))
+            })
+            .count();
+    }
+
+    /// The smallest rectangle around the area
+    pub fn bounds(&self) -> egui::Rect {
+        let mut bounds = egui::Rect::NOTHING;
+        for point in &self.points {
+            bounds.extend_with(point.to_pos2());
+        }
+        bounds
+    }
+
+    fn contains(&self, point: egui::Vec2) -> bool {
+        match self.shape {
+            AreaShape::Rectangle => self.bounds().contains(point.to_pos2()),
+            AreaShape::Lasso => {
+                // even-odd rule: count how often a ray to the right crosses the outline
+                let mut inside = false;
+                let count = self.points.len();
+                for index in 0..count {
+                    let a = self.points[index];
+                    let b = self.points[(index + count - 1) % count];
+                    if (a.y > point.y) != (b.y > point.y)
+                        && point.x < a.x + (point.y - a.y) / (b.y - a.y) * (b.x - a.x)
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// The island coordinates inside the bounds of the area. A town lies a little beside its
+    /// island, so these are not exactly the towns inside the area.
+    fn island_ranges(&self) -> (RangeInclusive<f32>, RangeInclusive<f32>) {
+        let bounds = self.bounds();
+        (
+            bounds.left().ceil()..=bounds.right().floor(),
+            bounds.top().ceil()..=bounds.bottom().floor(),
+        )
+    }
+
+    /// The islands inside the bounds of the area, as island coordinate constraints. Only a
+    /// rectangle can be written like this, a lasso is replaced by its bounds.
+    fn constraints(&self) -> [EmptyConstraint; 4] {
+        let (x, y) = self.island_ranges();
+        let constraint = |constraint_type, comparator, value: f32| EmptyConstraint {
+            constraint_type,
+            comparator,
+            value: format!("{value}"),
+        };
+        [
+            constraint(ConstraintType::IslandX, Comparator::GreaterThan, *x.start()),
+            constraint(ConstraintType::IslandX, Comparator::LessThan, *x.end()),
+            constraint(ConstraintType::IslandY, Comparator::GreaterThan, *y.start()),
+            constraint(ConstraintType::IslandY, Comparator::LessThan, *y.end()),
+        ]
+    }
+}
+
+impl View {
+    /// The window that offers what to do with the towns of a finished area
+    pub(crate) fn ui_area_selection(&mut self, ctx: &egui::Context) {
+        let Some(area) = &mut self.ui_data.area_selection else {
+            return;
+        };
+        if !area.finished {
+            return;
+        }
+
+        let mut open = true;
+        let mut create = false;
+        let mut add_constraint = false;
+        egui::Window::new(t!("map.area.title"))
+            .id(egui::Id::new("Area Selection Window"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(t!("map.area.towns", count = area.town_ids.len()));
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut area.name);
+                    let enabled = !area.town_ids.is_empty() && !area.name.is_empty();
+                    if ui
+                        .add_enabled(enabled, egui::Button::new(t!("map.area.create")))
+                        .clicked()
+                    {
+                        create = true;
+                    }
+                });
+
+                ui.separator();
+                ui.label(t!("map.area.island_towns", count = area.island_towns));
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("ComboBox Area Selection Target")
+                        .selected_text(&area.target)
+                        .show_ui(ui, |ui| {
+                            for selection in &self.ui_data.selections {
+                                ui.selectable_value(
+                                    &mut area.target,
+                                    selection.name.clone(),
+                                    &selection.name,
+                                );
+                            }
+                        });
+                    let join_mode = self
+                        .ui_data
+                        .selections
+                        .iter()
+                        .find(|selection| selection.name == area.target)
+                        .map(|selection| selection.constraint_join_mode);
+                    let mut button = ui.add_enabled(
+                        join_mode == Some(AndOr::And),
+                        egui::Button::new(t!("map.area.add_constraint")),
+                    );
+                    if join_mode == Some(AndOr::Or) {
+                        button = button.on_disabled_hover_text(t!("map.area.needs_and"));
+                    }
+                    if area.shape == AreaShape::Lasso {
+                        button = button.on_hover_text(t!("map.area.lasso_bounds"));
+                    }
+                    if button.clicked() {
+                        add_constraint = true;
+                    }
+                });
+            });
+
+        if create {
+            // a single constraint, an area can easily contain thousands of towns
+            let ids: Vec<String> = area.town_ids.iter().map(i32::to_string).collect();
+            let selection = EmptyTownSelection {
+                name: area.name.clone(),
+                constraints: vec![EmptyConstraint {
+                    constraint_type: ConstraintType::TownID,
+                    comparator: Comparator::InList,
+                    value: ids.join(", "),
+                }],
+                ..EmptyTownSelection::default()
+            };
+            self.add_selection(selection.fill());
+            open = false;
+        } else if add_constraint {
+            let target = area.target.clone();
+            for constraint in area.constraints() {
+                self.add_constraint_to(&target, &constraint);
+            }
+            open = false;
+        }
+        if !open {
+            self.ui_data.area_selection = None;
+        }
+    }
+}
@@ -6,9 +6,24 @@ use std::{
 
 use serde::{Deserialize, Serialize};
 
+use crate::bbcode::ListEntry;
+use crate::freeslot::{FreeSlot, FreeSlotFilter};
+use crate::island::Island;
+use crate::label::LabelStyle;
 use crate::selection::TownSelection;
+use crate::spatial::IndexCache;
+use crate::territory::Territory;
+use crate::tms::TmsReport;
+use crate::travel::TravelSettings;
+use crate::view::area::AreaSelection;
+use crate::view::image::ImageSettings;
+use crate::view::mesh::{HeatmapTextures, TerritoryTexture, TownMeshes};
 use crate::view::preferences::Preferences;
-use crate::{storage::SavedDB, town::Town};
+use crate::view::ruler::Ruler;
+use crate::{
+    storage::SavedDB,
+    town::{Town, TownDetails},
+};
 
 use super::preferences::DarkModePref;
 
@@ -20,6 +35,95 @@ pub const ALL_TOWNS_LIGHT: egui::Color32 = egui::Color32::from_gray(180);
 pub struct DefaultTownGroup {
     pub enabled: bool,
     pub color: egui::Color32,
+    #[serde(default)]
+    pub label: LabelStyle,
+}
+
+impl DefaultTownGroup {
+    fn default_changes() -> Self {
+        Self {
+            enabled: true,
+            color: egui::Color32::GOLD,
+            label: LabelStyle::default(),
+        }
+    }
+
+    fn default_free_slots() -> Self {
+        Self {
+            enabled: false,
+            color: egui::Color32::LIGHT_GREEN,
+            label: LabelStyle::default(),
+        }
+    }
+}
+
+/// The sea and the islands below the towns
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IslandSettings {
+    pub enabled: bool,
+    pub land: egui::Color32,
+    pub sea: egui::Color32,
+}
+
+impl Default for IslandSettings {
+    fn default() -> Self {
+        // translucent, so they work with the dark and the light theme
+        Self {
+            enabled: true,
+            land: egui::Color32::from_rgba_unmultiplied(110, 140, 70, 70),
+            sea: egui::Color32::from_rgba_unmultiplied(40, 90, 160, 40),
+        }
+    }
+}
+
+/// The regions controlled by each alliance, drawn below the towns
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TerritorySettings {
+    pub enabled: bool,
+    /// how far the influence of a town reaches, in world units
+    pub radius: u8,
+    /// of the regions, from 0 to 255. The borders are always opaque.
+    pub opacity: u8,
+}
+
+impl Default for TerritorySettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            radius: 20,
+            opacity: 80,
+        }
+    }
+}
+
+/// What the panel of a town knows about it
+#[derive(Debug, Clone)]
+pub enum TownPanelDetails {
+    /// the backend is busy
+    Loading,
+    /// the town does not exist in the loaded data
+    NotFound,
+    Failed(String),
+    Loaded(Arc<TownDetails>),
+}
+
+/// The panel of the town that was clicked on the map last
+#[derive(Debug, Clone)]
+pub struct TownPanel {
+    pub town_id: i32,
+    pub details: TownPanelDetails,
+    /// name of the selection the buttons add constraints to
+    pub target: String,
+}
+
+/// The outcome of importing a pasted list, shown until the user closes it
+#[derive(Debug, Clone)]
+pub struct ImportReport {
+    /// None if not a single entry of the list was found
+    pub selection_name: Option<String>,
+    pub missing: Vec<ListEntry>,
 }
 
 /// contains all the data required to draw the ui.
@@ -33,6 +137,18 @@ pub struct Data {
 
     pub settings_all: DefaultTownGroup,
     pub settings_ghosts: DefaultTownGroup,
+    #[serde(default = "DefaultTownGroup::default_changes")]
+    pub settings_changes: DefaultTownGroup,
+    #[serde(default = "DefaultTownGroup::default_free_slots")]
+    pub settings_free_slots: DefaultTownGroup,
+    #[serde(default)]
+    pub free_slot_filter: FreeSlotFilter,
+    #[serde(default)]
+    pub settings_islands: IslandSettings,
+    #[serde(default)]
+    pub settings_territory: TerritorySettings,
+    #[serde(default)]
+    pub settings_travel: TravelSettings,
 
     pub selections: Vec<TownSelection>,
 
@@ -40,6 +156,52 @@ pub struct Data {
     pub all_towns: Arc<Vec<Town>>,
     #[serde(skip)]
     pub ghost_towns: Arc<Vec<Town>>,
+    #[serde(skip)]
+    pub changed_towns: Arc<Vec<Town>>,
+    #[serde(skip)]
+    pub free_slots: Arc<Vec<FreeSlot>>,
+    #[serde(skip)]
+    pub islands: Arc<Vec<Island>>,
+    #[serde(skip)]
+    pub territory: Arc<Territory>,
+    /// built when a list of towns is first drawn, see `IndexCache`
+    #[serde(skip)]
+    pub town_indices: IndexCache<Town>,
+    #[serde(skip)]
+    pub slot_indices: IndexCache<FreeSlot>,
+    #[serde(skip)]
+    pub town_meshes: TownMeshes,
+    #[serde(skip)]
+    pub heatmap_textures: HeatmapTextures,
+    #[serde(skip)]
+    pub territory_texture: TerritoryTexture,
+
+    #[serde(skip)]
+    pub town_panel: Option<TownPanel>,
+    #[serde(skip)]
+    pub area_selection: Option<AreaSelection>,
+    /// None while the ruler tool is off
+    #[serde(skip)]
+    pub ruler: Option<Ruler>,
+    #[serde(skip)]
+    pub import_report: Option<ImportReport>,
+    #[serde(skip)]
+    pub tms_reports: Vec<TmsReport>,
+
+    #[serde(default)]
+    pub image_settings: ImageSettings,
+    #[serde(skip)]
+    pub show_image_export: bool,
+    /// why the last export failed, shown in the export window until the next try
+    #[serde(skip)]
+    pub image_export_error: Option<String>,
+    /// share of the image that is drawn while an export is running
+    #[serde(skip)]
+    pub image_export_progress: Option<f32>,
+
+    /// The snapshot that the loaded data is compared against
+    #[serde(skip)]
+    pub baseline: Option<SavedDB>,
 
     #[serde(skip)]
     pub saved_db: BTreeMap<String, Vec<SavedDB>>,
@@ -53,15 +215,42 @@ impl Default for Data {
             canvas: None,
             all_towns: Arc::new(Vec::new()),
             ghost_towns: Arc::new(Vec::new()),
+            changed_towns: Arc::new(Vec::new()),
+            free_slots: Arc::new(Vec::new()),
+            islands: Arc::new(Vec::new()),
+            territory: Arc::new(Territory::default()),
+            town_indices: IndexCache::default(),
+            slot_indices: IndexCache::default(),
+            town_meshes: TownMeshes::default(),
+            heatmap_textures: HeatmapTextures::default(),
+            territory_texture: TerritoryTexture::default(),
+            town_panel: None,
+            area_selection: None,
+            ruler: None,
+            import_report: None,
+            tms_reports: Vec::new(),
+            image_settings: ImageSettings::default(),
+            show_image_export: false,
+            image_export_error: None,
+            image_export_progress: None,
+            baseline: None,
             selections: vec![TownSelection::default()],
             settings_ghosts: DefaultTownGroup {
                 enabled: true,
                 color: egui::Color32::RED,
+                label: LabelStyle::default(),
             },
             settings_all: DefaultTownGroup {
                 enabled: true,
                 color: ALL_TOWNS_DARK,
+                label: LabelStyle::default(),
             },
+            settings_changes: DefaultTownGroup::default_changes(),
+            settings_free_slots: DefaultTownGroup::default_free_slots(),
+            free_slot_filter: FreeSlotFilter::default(),
+            settings_islands: IslandSettings::default(),
+            settings_territory: TerritorySettings::default(),
+            settings_travel: TravelSettings::default(),
             saved_db: BTreeMap::new(),
             preferences: Preferences::default(),
         }
@@ -104,6 +293,10 @@ pub struct CanvasData {
     /// distance from top left of canvas to top left of grepolis coordinates
     pub world_offset_px: egui::Vec2,
     pub zoom: f32,
+    /// world position the map should move to the center of the screen on the next frame
+    pub center_on: Option<egui::Vec2>,
+    /// the part of the world that was visible in the last frame
+    pub viewport: Option<egui::Rect>,
 }
 
 impl CanvasData {
@@ -111,6 +304,8 @@ impl CanvasData {
         Self {
             world_offset_px: top_left,
             zoom: 1.0,
+            center_on: None,
+            viewport: None,
         }
     }
 
@@ -164,11 +359,12 @@ impl ViewPortFilter {
         }
     }
 
-    pub fn town_in_viewport(&self, town: &Town) -> bool {
-        self.world_l < town.x
-            && town.x < self.world_r
-            && self.world_t < town.y
-            && town.y < self.world_b
+    /// Islands are positioned by their top left corner and are up to eight units wide
+    pub fn island_in_viewport(&self, island: &Island) -> bool {
+        self.world_l - 8.0 < island.x
+            && island.x < self.world_r
+            && self.world_t - 8.0 < island.y
+            && island.y < self.world_b
     }
 
     pub fn x_in_viewport(&self, x: f32) -> bool {
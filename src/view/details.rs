@@ -0,0 +1,198 @@
+use crate::bbcode::ListEntry;
+use crate::emptyconstraint::EmptyConstraint;
+use crate::selection::SelectionState;
+use crate::town::TownDetails;
+
+use super::data::TownPanelDetails;
+use super::View;
+
+/// A number that may be missing, e.g. the points of the alliance of a player without alliance
+fn optional(value: Option<u32>) -> String {
+    value.map_or_else(|| String::from("-"), |value| format!("{value}"))
+}
+
+impl View {
+    /// Add a constraint to the selection with the given name. A new selection starts with a
+    /// single constraint without value, which is replaced instead.
+    pub(super) fn add_constraint_to(&mut self, selection_name: &str, constraint: &EmptyConstraint) {
+        let Some(selection) = self
+            .ui_data
+            .selections
+            .iter_mut()
+            .find(|selection| selection.name == selection_name)
+        else {
+            return;
+        };
+        if selection.constraints.len() == 1 && selection.constraints[0].value.is_empty() {
+            selection.constraints.clear();
+        }
+        selection.constraints.push(constraint.fill());
+        // the sidepanel refreshes newly created selections and their dependents
+        selection.state = SelectionState::NewlyCreated;
+    }
+
+    fn ui_town_details(ui: &mut egui::Ui, details: &TownDetails) {
+        let town = &details.town;
+        let no_name = t!("selection.grouping.no_name");
+        egui::Grid::new("Grid Town Details").show(ui, |ui| {
+            for (label, value) in [
+                (t!("map.details.id"), format!("{}", town.id)),
+                (t!("map.details.name"), town.name.clone()),
+                (t!("map.details.points"), format!("{}", town.points)),
+                (
+                    t!("map.details.island"),
+                    format!(
+                        "{}|{} ({} {})",
+                        town.island_x,
+                        town.island_y,
+                        t!("map.details.slot"),
+                        town.slot_number
+                    ),
+                ),
+                (t!("map.details.ocean"), format!("{}", town.ocean())),
+            ] {
+                ui.label(label);
+                ui.label(value);
+                ui.end_row();
+            }
+
+            ui.label(t!("map.details.player"));
+            ui.label(town.player_name.clone().unwrap_or(no_name.clone()));
+            ui.end_row();
+            if town.player_name.is_some() {
+                ui.label("");
+                ui.label(t!(
+                    "map.details.player_stats",
+                    points = optional(details.player_points),
+                    rank = optional(details.player_rank),
+                    towns = optional(details.player_towns)
+                ));
+                ui.end_row();
+            }
+
+            ui.label(t!("map.details.alliance"));
+            ui.label(town.alliance_name.clone().unwrap_or(no_name));
+            ui.end_row();
+            if town.alliance_name.is_some() {
+                ui.label("");
+                ui.label(t!(
+                    "map.details.alliance_stats",
+                    points = optional(details.alliance_points),
+                    rank = optional(details.alliance_rank),
+                    members = optional(details.alliance_members)
+                ));
+                ui.end_row();
+            }
+        });
+    }
+
+    /// The panel of the town that was clicked on the map. Stays open until it is closed or
+    /// another town is clicked.
+    #[allow(clippy::too_many_lines)]
+    pub(crate) fn ui_town_panel(&mut self, ctx: &egui::Context) {
+        let Some(panel) = &mut self.ui_data.town_panel else {
+            return;
+        };
+        let mut open = true;
+        let mut constraint = None;
+        let mut center_on = None;
+
+        egui::Window::new(t!("map.details.title"))
+            .id(egui::Id::new("Town Panel Window"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let details = match &panel.details {
+                    TownPanelDetails::Loading => {
+                        ui.label(t!("map.details.loading", id = panel.town_id));
+                        return;
+                    }
+                    TownPanelDetails::NotFound => {
+                        ui.label(t!("map.details.not_found", id = panel.town_id));
+                        return;
+                    }
+                    TownPanelDetails::Failed(error) => {
+                        ui.colored_label(ui.visuals().error_fg_color, error);
+                        return;
+                    }
+                    TownPanelDetails::Loaded(details) => details,
+                };
+                let town = &details.town;
+                Self::ui_town_details(ui, details);
+                if ui.button(t!("map.details.center")).clicked() {
+                    center_on = Some(egui::vec2(town.x, town.y));
+                }
+                ui.separator();
+
+                let member_of: Vec<&str> = self
+                    .ui_data
+                    .selections
+                    .iter()
+                    .filter(|selection| selection.towns.iter().any(|t| t.id == town.id))
+                    .map(|selection| selection.name.as_str())
+                    .collect();
+                if member_of.is_empty() {
+                    ui.label(t!("map.details.in_no_selection"));
+                } else {
+                    ui.label(t!(
+                        "map.details.in_selections",
+                        names = member_of.join(", ")
+                    ));
+                }
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label(t!("map.details.add_to"));
+                    egui::ComboBox::from_id_source("ComboBox Town Panel Selection")
+                        .selected_text(&panel.target)
+                        .show_ui(ui, |ui| {
+                            for selection in &self.ui_data.selections {
+                                ui.selectable_value(
+                                    &mut panel.target,
+                                    selection.name.clone(),
+                                    &selection.name,
+                                );
+                            }
+                        });
+                });
+                let has_target = self
+                    .ui_data
+                    .selections
+                    .iter()
+                    .any(|selection| selection.name == panel.target);
+                ui.horizontal(|ui| {
+                    let entries = [
+                        (
+                            t!("map.details.add_town"),
+                            Some(ListEntry::Town(format!("{}", town.id))),
+                        ),
+                        (
+                            t!("map.details.add_player"),
+                            town.player_name.clone().map(ListEntry::Player),
+                        ),
+                        (
+                            t!("map.details.add_alliance"),
+                            town.alliance_name.clone().map(ListEntry::Alliance),
+                        ),
+                    ];
+                    for (text, entry) in entries {
+                        let enabled = has_target && entry.is_some();
+                        if ui.add_enabled(enabled, egui::Button::new(text)).clicked() {
+                            constraint = entry.map(|entry| entry.to_constraint());
+                        }
+                    }
+                });
+            });
+
+        let target = panel.target.clone();
+        if !open {
+            self.ui_data.town_panel = None;
+        }
+        if let Some(constraint) = constraint {
+            self.add_constraint_to(&target, &constraint);
+        }
+        if let (Some(target), Some(canvas)) = (center_on, self.ui_data.canvas.as_mut()) {
+            canvas.center_on = Some(target);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::selection::Grouping;
+use crate::town::TownGroup;
+
+use super::View;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum GroupColumn {
+    Name,
+    Players,
+    Towns,
+    Points,
+}
+
+impl ToString for GroupColumn {
+    fn to_string(&self) -> String {
+        match self {
+            GroupColumn::Name => t!("selection.grouping.column_name"),
+            GroupColumn::Players => t!("selection.grouping.column_players"),
+            GroupColumn::Towns => t!("selection.grouping.column_towns"),
+            GroupColumn::Points => t!("selection.grouping.column_points"),
+        }
+    }
+}
+
+impl GroupColumn {
+    fn compare(self, a: &TownGroup, b: &TownGroup) -> Ordering {
+        match self {
+            GroupColumn::Name => a
+                .name
+                .as_deref()
+                .map(str::to_lowercase)
+                .cmp(&b.name.as_deref().map(str::to_lowercase)),
+            GroupColumn::Players => a.players.cmp(&b.players),
+            GroupColumn::Towns => a.towns.cmp(&b.towns),
+            GroupColumn::Points => a.points.cmp(&b.points),
+        }
+    }
+}
+
+/// Which column the table of groups is sorted by. Only lives as long as the app runs.
+#[derive(Debug, Clone, Copy)]
+pub struct GroupSort {
+    pub column: GroupColumn,
+    pub descending: bool,
+}
+
+impl Default for GroupSort {
+    fn default() -> Self {
+        Self {
+            column: GroupColumn::Points,
+            descending: true,
+        }
+    }
+}
+
+impl View {
+    /// One window for every selection that lists its towns grouped by player or alliance
+    pub fn ui_group_windows(&mut self, ctx: &egui::Context) {
+        for (selection_index, selection) in self.ui_data.selections.iter_mut().enumerate() {
+            if selection.grouping == Grouping::Towns {
+                continue;
+            }
+
+            let mut open = true;
+            egui::Window::new(format!(
+                "{} - {}",
+                selection.name,
+                selection.grouping.to_string()
+            ))
+            .id(egui::Id::new(format!("Group Window {selection_index}")))
+            .open(&mut open)
+            .default_width(350.0)
+            .show(ctx, |ui| {
+                let mut rows: Vec<&TownGroup> = selection.groups.iter().collect();
+                let sort = selection.group_sort;
+                rows.sort_by(|a, b| {
+                    let ordering = sort.column.compare(a, b);
+                    if sort.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                });
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new(format!("Group Grid {selection_index}"))
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for column in GroupColumn::iter() {
+                                // a list of players has exactly one player per row
+                                if column == GroupColumn::Players
+                                    && selection.grouping == Grouping::Players
+                                {
+                                    continue;
+                                }
+                                let arrow = match (sort.column == column, sort.descending) {
+                                    (false, _) => "",
+                                    (true, true) => " ⏷",
+                                    (true, false) => " ⏶",
+                                };
+                                let text = format!("{}{arrow}", column.to_string());
+                                if ui.selectable_label(sort.column == column, text).clicked() {
+                                    selection.group_sort = if sort.column == column {
+                                        GroupSort {
+                                            column,
+                                            descending: !sort.descending,
+                                        }
+                                    } else {
+                                        GroupSort {
+                                            column,
+                                            descending: column != GroupColumn::Name,
+                                        }
+                                    };
+                                }
+                            }
+                            ui.end_row();
+
+                            for row in rows {
+                                ui.label(
+                                    row.name.clone().unwrap_or(t!("selection.grouping.no_name")),
+                                );
+                                if selection.grouping != Grouping::Players {
+                                    ui.label(format!("{}", row.players));
+                                }
+                                ui.label(format!("{}", row.towns));
+                                ui.label(format!("{}", row.points));
+                                ui.end_row();
+                            }
+                        });
+                });
+            });
+
+            // closing the window goes back to a plain list of towns
+            if !open {
+                selection.grouping = Grouping::Towns;
+                selection.groups = std::sync::Arc::new(Vec::new());
+            }
+        }
+    }
+}
@@ -0,0 +1,584 @@
+use anyhow::Context;
+use native_dialog::FileDialog;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::island::ISLAND_ZOOM;
+use crate::label::{place_labels, LabelStyle, LABEL_ZOOM};
+use crate::mapimage::{ImageFormat, Scene, Shape, TextMeasure};
+use crate::message::MessageToModel;
+use crate::selection::TownSelection;
+use crate::town::Town;
+
+use super::data::Data;
+use super::{View, UNICODE_FONT};
+
+/// Neither side of an exported image may be larger than this many pixels
+const MAX_SIDE: u32 = 16384;
+/// A PNG is drawn in memory with four bytes per pixel, so it may not be larger than 256 MiB
+const MAX_PIXELS: u64 = 64 * 1024 * 1024;
+
+/// The part of the world that is exported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, EnumIter)]
+pub enum ImageArea {
+    World,
+    View,
+    Custom,
+}
+
+impl ToString for ImageArea {
+    fn to_string(&self) -> String {
+        match self {
+            ImageArea::World => t!("menu.image.area_world"),
+            ImageArea::View => t!("menu.image.area_view"),
+            ImageArea::Custom => t!("menu.image.area_custom"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImageSettings {
+    pub format: ImageFormat,
+    pub area: ImageArea,
+    /// the custom area, in island coordinates
+    pub x_min: u16,
+    pub x_max: u16,
+    pub y_min: u16,
+    pub y_max: u16,
+    /// in pixels, the height follows from the area
+    pub width: u32,
+    pub grid: bool,
+    pub legend: bool,
+}
+
+impl Default for ImageSettings {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::Png,
+            area: ImageArea::World,
+            x_min: 0,
+            x_max: 1000,
+            y_min: 0,
+            y_max: 1000,
+            width: 4000,
+            grid: true,
+            legend: true,
+        }
+    }
+}
+
+impl ImageSettings {
+    /// The exported part of the world, in world coordinates
+    fn world_rect(&self, viewport: Option<egui::Rect>) -> egui::Rect {
+        let world = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1000.0, 1000.0));
+        match self.area {
+            ImageArea::World => world,
+            ImageArea::View => viewport.unwrap_or(world),
+            ImageArea::Custom => egui::Rect::from_two_pos(
+                egui::pos2(f32::from(self.x_min), f32::from(self.y_min)),
+                egui::pos2(f32::from(self.x_max), f32::from(self.y_max)),
+            ),
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    fn height(&self, area: egui::Rect) -> u32 {
+        (self.width as f32 * area.height() / area.width()).round() as u32
+    }
+}
+
+/// A colour bar with the smallest and largest value for every selection that is coloured by
+/// a gradient, stacked in the bottom left corner of `rect`. Used for the map and for exported
+/// images, which make it larger with `scale`.
+pub fn gradient_legends(
+    rect: egui::Rect,
+    selections: &[TownSelection],
+    visuals: &egui::Visuals,
+    scale: f32,
+) -> Vec<Shape> {
+    const STEPS: u8 = 32;
+    let bar_size = egui::vec2(160.0, 10.0) * scale;
+    let text_size = 12.0 * scale;
+    let margin = 4.0 * scale;
+    let text_color = visuals.text_color();
+
+    let mut shapes = Vec::new();
+    let mut bottom = rect.bottom() - 2.0 * margin;
+    for selection in selections.iter().filter(|s| !s.is_hidden()) {
+        let Some(gradient) = selection.gradient else {
+            continue;
+        };
+        let values = &selection.gradient_values;
+        let left = rect.left() + 2.0 * margin;
+        let legend = egui::Rect::from_min_max(
+            egui::pos2(left, bottom - 2.0 * bar_size.y - 6.0 * margin),
+            egui::pos2(left + bar_size.x + 2.0 * margin, bottom),
+        );
+        shapes.push(Shape::Rect {
+            rect: legend,
+            fill: visuals.extreme_bg_color,
+        });
+
+        shapes.push(Shape::Text {
+            pos: legend.left_top() + egui::vec2(margin, margin / 2.0),
+            anchor: egui::Align2::LEFT_TOP,
+            text: format!("{} - {}", selection.name, gradient.attribute.to_string()),
+            size: text_size,
+            color: text_color,
+        });
+
+        let bar_top = legend.top() + 4.5 * margin;
+        let step_width = bar_size.x / f32::from(STEPS);
+        for step in 0..STEPS {
+            let step_left = legend.left() + margin + f32::from(step) * step_width;
+            shapes.push(Shape::Rect {
+                rect: egui::Rect::from_min_size(
+                    egui::pos2(step_left, bar_top),
+                    egui::vec2(step_width + 0.5, bar_size.y),
+                ),
+                fill: gradient.color_at(f32::from(step) / f32::from(STEPS - 1)),
+            });
+        }
+
+        let labels_top = bar_top + bar_size.y + margin / 2.0;
+        shapes.push(Shape::Text {
+            pos: egui::pos2(legend.left() + margin, labels_top),
+            anchor: egui::Align2::LEFT_TOP,
+            text: format!("{}", values.min),
+            size: text_size,
+            color: text_color,
+        });
+        shapes.push(Shape::Text {
+            pos: egui::pos2(legend.right() - margin, labels_top),
+            anchor: egui::Align2::RIGHT_TOP,
+            text: format!("{}", values.max),
+            size: text_size,
+            color: text_color,
+        });
+
+        bottom = legend.top() - 1.5 * margin;
+    }
+    shapes
+}
+
+/// The lines between the territories of the alliances
+pub fn territory_border(visuals: &egui::Visuals) -> egui::Stroke {
+    egui::Stroke::new(1.0, visuals.text_color())
+}
+
+/// Draw the layers of the map, the grid and the legends into an image of the given size. Does
+/// not need a window, only the data and the colours of the theme.
+#[allow(clippy::too_many_lines, clippy::cast_precision_loss)]
+pub fn map_scene(
+    data: &Data,
+    settings: &ImageSettings,
+    visuals: &egui::Visuals,
+) -> anyhow::Result<Scene> {
+    let viewport = data.canvas.as_ref().and_then(|canvas| canvas.viewport);
+    let area = settings.world_rect(viewport);
+    if area.width() < 1.0 || area.height() < 1.0 {
+        return Err(anyhow::anyhow!("The area of the image is empty"));
+    }
+    let (width, height) = (settings.width, settings.height(area));
+    if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+        return Err(anyhow::anyhow!(
+            "An image of {width}x{height} pixels is too large or too small, each side must be between 1 and {MAX_SIDE} pixels"
+        ));
+    }
+    if settings.format == ImageFormat::Png && u64::from(width) * u64::from(height) > MAX_PIXELS {
+        return Err(anyhow::anyhow!(
+            "A PNG of {width}x{height} pixels is too large, it may have at most {MAX_PIXELS} pixels"
+        ));
+    }
+
+    let zoom = width as f32 / area.width();
+    let to_image = |x: f32, y: f32| egui::pos2((x - area.left()) * zoom, (y - area.top()) * zoom);
+    let in_area = |town: &&Town| area.contains(egui::pos2(town.x, town.y));
+    let radius = 1.0 + 0.15 * zoom;
+    let mut shapes = Vec::new();
+
+    // SEA AND ISLANDS
+    if data.settings_islands.enabled {
+        let islands = &data.settings_islands;
+        shapes.push(Shape::Rect {
+            rect: egui::Rect::from_min_max(to_image(0.0, 0.0), to_image(1000.0, 1000.0)),
+            fill: islands.sea,
+        });
+        for island in data
+            .islands
+            .iter()
+            .filter(|island| area.expand(8.0).contains(egui::pos2(island.x, island.y)))
+        {
+            if zoom > ISLAND_ZOOM && island.outline.len() > 2 {
+                shapes.push(Shape::Polygon {
+                    points: island
+                        .outline
+                        .iter()
+                        .map(|corner| to_image(island.x + corner.x, island.y + corner.y))
+                        .collect(),
+                    fill: islands.land,
+                });
+            } else {
+                let center = island.center();
+                shapes.push(Shape::Circle {
+                    center: to_image(center.x, center.y),
+                    radius: 0.5 + zoom,
+                    fill: islands.land,
+                });
+            }
+        }
+    }
+
+    // GRID
+    if settings.grid {
+        let line = |shapes: &mut Vec<Shape>, i: f32, stroke: egui::Stroke, dashed: bool| {
+            shapes.push(Shape::Line {
+                points: [to_image(0.0, i), to_image(1000.0, i)],
+                stroke,
+                dashed,
+            });
+            shapes.push(Shape::Line {
+                points: [to_image(i, 0.0), to_image(i, 1000.0)],
+                stroke,
+                dashed,
+            });
+        };
+        for i in (0u16..=10).map(|i| f32::from(i) * 100.0) {
+            line(
+                &mut shapes,
+                i,
+                egui::Stroke::new(2.0, egui::Color32::DARK_GRAY),
+                false,
+            );
+        }
+        if zoom > 5.0 {
+            for i in (0u16..=100).map(|i| f32::from(i) * 10.0).filter(|i| {
+                (area.left()..=area.right()).contains(i) || (area.top()..=area.bottom()).contains(i)
+            }) {
+                line(
+                    &mut shapes,
+                    i,
+                    egui::Stroke::new(1.0, egui::Color32::DARK_GRAY),
+                    true,
+                );
+            }
+        }
+    }
+
+    // ALLIANCE TERRITORIES
+    if data.settings_territory.enabled {
+        let colors = data
+            .territory
+            .colors(&data.selections, data.settings_territory.opacity);
+        for (rect, alliance) in data.territory.regions() {
+            if rect.intersects(area) {
+                shapes.push(Shape::Rect {
+                    rect: egui::Rect::from_min_max(
+                        to_image(rect.left(), rect.top()),
+                        to_image(rect.right(), rect.bottom()),
+                    ),
+                    fill: colors[alliance],
+                });
+            }
+        }
+        let border = territory_border(visuals);
+        for [a, b] in &data.territory.borders {
+            if area.intersects(egui::Rect::from_two_pos(*a, *b)) {
+                shapes.push(Shape::Line {
+                    points: [to_image(a.x, a.y), to_image(b.x, b.y)],
+                    stroke: border,
+                    dashed: false,
+                });
+            }
+        }
+    }
+
+    // HEATMAPS, below all towns
+    for selection in data.selections.iter().rev() {
+        if selection.heatmap.is_some() && !selection.is_hidden() {
+            for (rect, fill) in selection.heatmap_values.regions(selection.color) {
+                if rect.intersects(area) {
+                    shapes.push(Shape::Rect {
+                        rect: egui::Rect::from_min_max(
+                            to_image(rect.left(), rect.top()),
+                            to_image(rect.right(), rect.bottom()),
+                        ),
+                        fill,
+                    });
+                }
+            }
+        }
+    }
+
+    // LAYERS, in the same order as on the map
+    let circles = |towns: &[Town], radius: f32, fill: egui::Color32| -> Vec<Shape> {
+        towns
+            .iter()
+            .filter(in_area)
+            .map(|town| Shape::Circle {
+                center: to_image(town.x, town.y),
+                radius,
+                fill,
+            })
+            .collect()
+    };
+    if data.settings_all.enabled {
+        shapes.extend(circles(&data.all_towns, radius, data.settings_all.color));
+    }
+    if data.settings_free_slots.enabled {
+        for slot in data
+            .free_slots
+            .iter()
+            .filter(|slot| area.contains(egui::pos2(slot.x, slot.y)))
+        {
+            shapes.push(Shape::Ring {
+                center: to_image(slot.x, slot.y),
+                radius,
+                stroke: egui::Stroke::new(1.0, data.settings_free_slots.color),
+            });
+        }
+    }
+    if data.settings_ghosts.enabled {
+        shapes.extend(circles(
+            &data.ghost_towns,
+            radius + 1.0,
+            data.settings_ghosts.color,
+        ));
+    }
+    if data.settings_changes.enabled && data.baseline.is_some() {
+        shapes.extend(circles(
+            &data.changed_towns,
+            radius + 1.0,
+            data.settings_changes.color,
+        ));
+    }
+    for selection in &data.selections {
+        let values = &selection.gradient_values;
+        for town in selection.towns.iter().filter(in_area) {
+            let fill = match (selection.gradient, values.values.get(&town.id)) {
+                (Some(gradient), Some(&value)) => gradient.color(value, values.min, values.max),
+                _ => selection.color,
+            };
+            shapes.push(Shape::Circle {
+                center: to_image(town.x, town.y),
+                radius,
+                fill,
+            });
+        }
+    }
+
+    // LEGEND
+    if settings.legend {
+        let image_rect =
+            egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(width as f32, height as f32));
+        let scale = (width as f32 / 1000.0).max(1.0);
+        shapes.extend(gradient_legends(
+            image_rect,
+            &data.selections,
+            visuals,
+            scale,
+        ));
+    }
+
+    // LABELS, only if the image is zoomed in as far as the map has to be. The layers on top
+    // place their labels first.
+    if zoom > LABEL_ZOOM {
+        let mut labels: Vec<(&Town, LabelStyle, egui::Color32)> = Vec::new();
+        for selection in data
+            .selections
+            .iter()
+            .rev()
+            .filter(|s| !s.is_hidden() && s.label.is_enabled())
+        {
+            labels.extend(
+                selection
+                    .towns
+                    .iter()
+                    .filter(in_area)
+                    .map(|town| (town, selection.label, selection.color)),
+            );
+        }
+        for (settings, towns, enabled) in [
+            (
+                &data.settings_changes,
+                &data.changed_towns,
+                data.baseline.is_some(),
+            ),
+            (&data.settings_ghosts, &data.ghost_towns, true),
+            (&data.settings_all, &data.all_towns, true),
+        ] {
+            if enabled && settings.enabled && settings.label.is_enabled() {
+                labels.extend(
+                    towns
+                        .iter()
+                        .filter(in_area)
+                        .map(|town| (town, settings.label, settings.color)),
+                );
+            }
+        }
+        let measure = TextMeasure::new(UNICODE_FONT)?;
+        let placed = place_labels(
+            &labels,
+            2.0 + 0.15 * zoom,
+            |town| to_image(town.x, town.y),
+            |text, size| measure.size(text, size),
+        );
+        shapes.extend(placed.into_iter().map(|label| Shape::Text {
+            pos: label.rect.left_center(),
+            anchor: egui::Align2::LEFT_CENTER,
+            text: label.text.to_owned(),
+            size: label.size,
+            color: label.color,
+        }));
+    }
+
+    Ok(Scene {
+        width,
+        height,
+        background: visuals.extreme_bg_color,
+        shapes,
+    })
+}
+
+impl View {
+    /// Ask where to save the image and let the backend draw it. Returns false if the user
+    /// cancelled.
+    fn save_map_image(&self, ctx: &egui::Context) -> anyhow::Result<bool> {
+        let settings = &self.ui_data.image_settings;
+        // build the scene first, so a bad size is reported before the file chooser opens
+        let scene = map_scene(&self.ui_data, settings, &ctx.style().visuals)?;
+        let file_opt = FileDialog::new()
+            .set_filename(&format!(
+                "{}.{}",
+                self.ui_data.server_id,
+                settings.format.extension()
+            ))
+            .add_filter(settings.format.name(), &[settings.format.extension()])
+            .show_save_single_file()
+            .context("Failed to open a file chooser")?;
+        let Some(file_path) = file_opt else {
+            // the user knowingly clicked cancel
+            return Ok(false);
+        };
+        self.channel_presenter_tx
+            .send(MessageToModel::ExportImage(
+                scene,
+                settings.format,
+                file_path,
+                ctx.clone(),
+            ))
+            .context("Failed to send the ExportImage message to the backend")?;
+        Ok(true)
+    }
+
+    /// The window in which the map is exported as an image
+    pub(crate) fn ui_image_export(&mut self, ctx: &egui::Context) {
+        let mut open = self.ui_data.show_image_export;
+        let mut save = false;
+        let viewport = self
+            .ui_data
+            .canvas
+            .as_ref()
+            .and_then(|canvas| canvas.viewport);
+        let settings = &mut self.ui_data.image_settings;
+
+        egui::Window::new(t!("menu.image.title"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                egui::Grid::new("Grid Image Export").show(ui, |ui| {
+                    ui.label(t!("menu.image.format"));
+                    egui::ComboBox::from_id_source("ComboBox Image Format")
+                        .selected_text(settings.format.name())
+                        .show_ui(ui, |ui| {
+                            for format in ImageFormat::iter() {
+                                ui.selectable_value(&mut settings.format, format, format.name());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label(t!("menu.image.area"));
+                    egui::ComboBox::from_id_source("ComboBox Image Area")
+                        .selected_text(settings.area.to_string())
+                        .show_ui(ui, |ui| {
+                            for area in ImageArea::iter() {
+                                ui.selectable_value(&mut settings.area, area, area.to_string());
+                            }
+                        });
+                    ui.end_row();
+
+                    if settings.area == ImageArea::Custom {
+                        ui.label("X");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut settings.x_min).clamp_range(0..=1000));
+                            ui.label("-");
+                            ui.add(egui::DragValue::new(&mut settings.x_max).clamp_range(0..=1000));
+                        });
+                        ui.end_row();
+                        ui.label("Y");
+                        ui.horizontal(|ui| {
+                            ui.add(egui::DragValue::new(&mut settings.y_min).clamp_range(0..=1000));
+                            ui.label("-");
+                            ui.add(egui::DragValue::new(&mut settings.y_max).clamp_range(0..=1000));
+                        });
+                        ui.end_row();
+                    }
+
+                    ui.label(t!("menu.image.width"));
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut settings.width)
+                                .clamp_range(100..=MAX_SIDE)
+                                .speed(10)
+                                .suffix(" px"),
+                        );
+                        let area = settings.world_rect(viewport);
+                        if area.width() >= 1.0 {
+                            ui.label(format!("x {} px", settings.height(area)));
+                        }
+                    });
+                    ui.end_row();
+                });
+
+                ui.checkbox(&mut settings.grid, t!("menu.image.grid"));
+                ui.checkbox(&mut settings.legend, t!("menu.image.legend"));
+                ui.separator();
+                let running = self.ui_data.image_export_progress;
+                if ui
+                    .add_enabled(running.is_none(), egui::Button::new(t!("menu.image.save")))
+                    .clicked()
+                {
+                    save = true;
+                }
+                if let Some(share) = running {
+                    ui.add(
+                        egui::ProgressBar::new(share)
+                            .text(t!("menu.image.saving"))
+                            .animate(true),
+                    );
+                }
+                if let Some(error) = &self.ui_data.image_export_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+            });
+
+        self.ui_data.show_image_export = open;
+        if save {
+            match self.save_map_image(ctx) {
+                Ok(started) => {
+                    self.ui_data.image_export_error = None;
+                    if started {
+                        self.ui_data.image_export_progress = Some(0.0);
+                    }
+                }
+                Err(err) => self.ui_data.image_export_error = Some(format!("{err:#}")),
+            }
+        }
+    }
+}
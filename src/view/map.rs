@@ -1,13 +1,115 @@
+use std::sync::Arc;
+
 use egui::Shape;
 
-use crate::town::Town;
+use crate::{
+    island::ISLAND_ZOOM,
+    label::{place_labels, LabelStyle, LABEL_ZOOM},
+    mapimage::Shape as ImageShape,
+    message::MessageToModel,
+    spatial::SpatialIndex,
+    town::Town,
+};
 
 use super::{
-    data::{CanvasData, ViewPortFilter},
+    area::{AreaSelection, AreaShape, LASSO_STEP_PX},
+    data::{CanvasData, TownPanel, TownPanelDetails, ViewPortFilter},
+    image::{gradient_legends, territory_border},
+    ruler::RulerPoint,
     View,
 };
 
+/// The entries of a list that lie in the viewport
+fn visible<'a, T>(list: &'a [T], index: &SpatialIndex, viewport: egui::Rect) -> Vec<&'a T> {
+    index.in_rect(viewport).map(|entry| &list[entry]).collect()
+}
+
 impl View {
+    /// Draw a shape of an exported image on the map
+    fn paint_shape(painter: &egui::Painter, shape: ImageShape) {
+        match shape {
+            ImageShape::Circle {
+                center,
+                radius,
+                fill,
+            } => {
+                painter.circle_filled(center, radius, fill);
+            }
+            ImageShape::Ring {
+                center,
+                radius,
+                stroke,
+            } => {
+                painter.circle_stroke(center, radius, stroke);
+            }
+            ImageShape::Line {
+                points,
+                stroke,
+                dashed,
+            } => {
+                if dashed {
+                    painter.add(egui::Shape::dashed_line(&points, stroke, 7.0, 7.0));
+                } else {
+                    painter.line_segment(points, stroke);
+                }
+            }
+            ImageShape::Rect { rect, fill } => {
+                painter.rect_filled(rect, 0.0, fill);
+            }
+            ImageShape::Polygon { points, fill } => {
+                painter.add(egui::Shape::convex_polygon(
+                    points,
+                    fill,
+                    egui::Stroke::NONE,
+                ));
+            }
+            ImageShape::Text {
+                pos,
+                anchor,
+                text,
+                size,
+                color,
+            } => {
+                painter.text(pos, anchor, text, egui::FontId::proportional(size), color);
+            }
+        }
+    }
+
+    /// Write the labels next to their towns, see `place_labels`
+    fn draw_labels(
+        painter: &egui::Painter,
+        canvas_data: &CanvasData,
+        labels: &[(&Town, LabelStyle, egui::Color32)],
+    ) {
+        let placed = place_labels(
+            labels,
+            2.0 + canvas_data.scale_world_to_screen(0.15),
+            |town| {
+                canvas_data
+                    .world_to_screen(egui::vec2(town.x, town.y))
+                    .to_pos2()
+            },
+            |text, size| {
+                painter
+                    .layout_no_wrap(
+                        text.to_owned(),
+                        egui::FontId::proportional(size),
+                        egui::Color32::WHITE,
+                    )
+                    .size()
+            },
+        );
+        for label in placed {
+            painter.text(
+                label.rect.left_center(),
+                egui::Align2::LEFT_CENTER,
+                label.text,
+                egui::FontId::proportional(label.size),
+                label.color,
+            );
+        }
+    }
+
     #[allow(clippy::too_many_lines)] // UI Code, am I right, hahah
     pub fn ui_map(&mut self, ctx: &egui::Context) {
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -26,9 +128,49 @@ impl View {
                 // Some() just a line above this comment.
                 let canvas_data = self.ui_data.canvas.as_mut().unwrap();
 
-                //DRAG
-                canvas_data.world_offset_px -=
-                    canvas_data.scale_screen_to_world(response.drag_delta());
+                // CENTER, e.g. on a town that was clicked in a table
+                if let Some(target) = canvas_data.center_on.take() {
+                    canvas_data.world_offset_px = target
+                        - canvas_data.scale_screen_to_world(response.rect.center().to_vec2());
+                }
+
+                // SHIFT + DRAG: RECTANGLE, CTRL + DRAG: LASSO
+                if response.drag_started() {
+                    let modifiers = ctx.input(|input| input.modifiers);
+                    let shape = if modifiers.shift {
+                        Some(AreaShape::Rectangle)
+                    } else if modifiers.command {
+                        Some(AreaShape::Lasso)
+                    } else {
+                        None
+                    };
+                    if let (Some(shape), Some(start)) = (shape, response.interact_pointer_pos()) {
+                        let start = canvas_data.screen_to_world(start.to_vec2());
+                        self.ui_data.area_selection = Some(AreaSelection::new(shape, start));
+                    }
+                }
+                let drawing_area = self
+                    .ui_data
+                    .area_selection
+                    .as_ref()
+                    .is_some_and(|area| !area.finished);
+                if drawing_area {
+                    let area = self.ui_data.area_selection.as_mut().unwrap();
+                    if let Some(position) = response.interact_pointer_pos() {
+                        area.extend(
+                            canvas_data.screen_to_world(position.to_vec2()),
+                            canvas_data.scale_screen_to_world(LASSO_STEP_PX),
+                        );
+                    }
+                    if response.drag_released() {
+                        let index = self.ui_data.town_indices.get(&self.ui_data.all_towns);
+                        area.finish(&self.ui_data.all_towns, &index);
+                    }
+                } else {
+                    //DRAG
+                    canvas_data.world_offset_px -=
+                        canvas_data.scale_screen_to_world(response.drag_delta());
+                }
 
                 // ZOOM
                 // as per https://www.youtube.com/watch?v=ZQ8qtAizis4
@@ -62,18 +204,72 @@ impl View {
 
                 // filter everything that is not visible
                 let filter = ViewPortFilter::new(canvas_data, response.rect);
-                let visible_towns_all: Vec<&Town> = self
-                    .ui_data
-                    .all_towns
-                    .iter()
-                    .filter(|town| filter.town_in_viewport(town))
-                    .collect();
-                let visible_ghost_towns: Vec<&Town> = self
-                    .ui_data
-                    .ghost_towns
-                    .iter()
-                    .filter(|town| filter.town_in_viewport(town))
-                    .collect();
+                let viewport = egui::Rect::from_min_max(
+                    canvas_data
+                        .screen_to_world(response.rect.left_top().to_vec2())
+                        .to_pos2(),
+                    canvas_data
+                        .screen_to_world(response.rect.right_bottom().to_vec2())
+                        .to_pos2(),
+                );
+                canvas_data.viewport = Some(viewport);
+                let towns_index = self.ui_data.town_indices.get(&self.ui_data.all_towns);
+                let visible_towns_all = visible(&self.ui_data.all_towns, &towns_index, viewport);
+                let visible_ghost_towns = visible(
+                    &self.ui_data.ghost_towns,
+                    &self.ui_data.town_indices.get(&self.ui_data.ghost_towns),
+                    viewport,
+                );
+                let visible_changed_towns = visible(
+                    &self.ui_data.changed_towns,
+                    &self.ui_data.town_indices.get(&self.ui_data.changed_towns),
+                    viewport,
+                );
+                let slots_index = self.ui_data.slot_indices.get(&self.ui_data.free_slots);
+                let visible_free_slots = visible(&self.ui_data.free_slots, &slots_index, viewport);
+
+                // DRAW SEA AND ISLANDS
+                if self.ui_data.settings_islands.enabled {
+                    let settings = &self.ui_data.settings_islands;
+                    painter.rect_filled(
+                        egui::Rect::from_min_max(
+                            canvas_data.world_to_screen(egui::vec2(0.0, 0.0)).to_pos2(),
+                            canvas_data
+                                .world_to_screen(egui::vec2(1000.0, 1000.0))
+                                .to_pos2(),
+                        ),
+                        0.0,
+                        settings.sea,
+                    );
+                    for island in self
+                        .ui_data
+                        .islands
+                        .iter()
+                        .filter(|island| filter.island_in_viewport(island))
+                    {
+                        if canvas_data.zoom > ISLAND_ZOOM && island.outline.len() > 2 {
+                            let position = egui::vec2(island.x, island.y);
+                            let points = island
+                                .outline
+                                .iter()
+                                .map(|corner| {
+                                    canvas_data.world_to_screen(position + *corner).to_pos2()
+                                })
+                                .collect();
+                            painter.add(Shape::convex_polygon(
+                                points,
+                                settings.land,
+                                egui::Stroke::NONE,
+                            ));
+                        } else {
+                            painter.circle_filled(
+                                canvas_data.world_to_screen(island.center()).to_pos2(),
+                                0.5 + canvas_data.scale_world_to_screen(1.0),
+                                settings.land,
+                            );
+                        }
+                    }
+                }
 
                 // DRAW GRID
                 for i in (0u16..=10).map(|i| f32::from(i) * 100.0) {
@@ -114,48 +310,260 @@ impl View {
                     }
                 }
 
+                // DRAW ALLIANCE TERRITORIES
+                if self.ui_data.settings_territory.enabled {
+                    self.ui_data.territory_texture.draw(
+                        &painter,
+                        canvas_data,
+                        &self.ui_data.territory,
+                        &self.ui_data.selections,
+                        self.ui_data.settings_territory.opacity,
+                        territory_border(ui.visuals()),
+                    );
+                }
+
+                // DRAW HEATMAPS
+                // below all towns, so the dots stay visible
+                for selection in self.ui_data.selections.iter().rev() {
+                    if selection.heatmap.is_some() && !selection.is_hidden() {
+                        self.ui_data.heatmap_textures.draw(
+                            &painter,
+                            canvas_data,
+                            &selection.heatmap_values,
+                            selection.color,
+                        );
+                    }
+                }
+                self.ui_data.heatmap_textures.end_frame();
+
                 // DRAW ALL TOWNS
                 // towns have a diameter of .25 units, approximately
                 if self.ui_data.settings_all.enabled {
-                    for town in &visible_towns_all {
-                        painter.circle_filled(
+                    let color = self.ui_data.settings_all.color;
+                    self.ui_data.town_meshes.draw(
+                        &painter,
+                        canvas_data,
+                        &self.ui_data.all_towns,
+                        &visible_towns_all,
+                        1.0 + canvas_data.scale_world_to_screen(0.15),
+                        color,
+                        |_| color,
+                    );
+                }
+
+                // DRAW FREE SLOTS
+                // as rings, so they can't be mistaken for towns
+                if self.ui_data.settings_free_slots.enabled {
+                    for slot in &visible_free_slots {
+                        painter.circle_stroke(
                             canvas_data
-                                .world_to_screen(egui::vec2(town.x, town.y))
+                                .world_to_screen(egui::vec2(slot.x, slot.y))
                                 .to_pos2(),
                             1.0 + canvas_data.scale_world_to_screen(0.15),
-                            self.ui_data.settings_all.color,
+                            egui::Stroke::new(1.0, self.ui_data.settings_free_slots.color),
                         );
                     }
                 }
 
                 // DRAW GHOST TOWNS
                 if self.ui_data.settings_ghosts.enabled {
-                    for town in &visible_ghost_towns {
-                        painter.circle_filled(
-                            canvas_data
-                                .world_to_screen(egui::vec2(town.x, town.y))
-                                .to_pos2(),
-                            2.0 + canvas_data.scale_world_to_screen(0.15),
-                            self.ui_data.settings_ghosts.color,
-                        );
-                    }
+                    let color = self.ui_data.settings_ghosts.color;
+                    self.ui_data.town_meshes.draw(
+                        &painter,
+                        canvas_data,
+                        &self.ui_data.ghost_towns,
+                        &visible_ghost_towns,
+                        2.0 + canvas_data.scale_world_to_screen(0.15),
+                        color,
+                        |_| color,
+                    );
+                }
+
+                // DRAW CHANGED TOWNS
+                if self.ui_data.settings_changes.enabled && self.ui_data.baseline.is_some() {
+                    let color = self.ui_data.settings_changes.color;
+                    self.ui_data.town_meshes.draw(
+                        &painter,
+                        canvas_data,
+                        &self.ui_data.changed_towns,
+                        &visible_changed_towns,
+                        2.0 + canvas_data.scale_world_to_screen(0.15),
+                        color,
+                        |_| color,
+                    );
                 }
 
                 // DRAW SELECTED TOWS
                 for selection in &self.ui_data.selections {
-                    for town in selection
-                        .towns
+                    let values = &selection.gradient_values;
+                    let index = self.ui_data.town_indices.get(&selection.towns);
+                    self.ui_data.town_meshes.draw(
+                        &painter,
+                        canvas_data,
+                        &selection.towns,
+                        &visible(&selection.towns, &index, viewport),
+                        1.0 + canvas_data.scale_world_to_screen(0.15),
+                        (selection.color, selection.gradient, Arc::as_ptr(values)),
+                        // towns without a value keep the colour of the selection
+                        |town| match (selection.gradient, values.values.get(&town.id)) {
+                            (Some(gradient), Some(&value)) => {
+                                gradient.color(value, values.min, values.max)
+                            }
+                            _ => selection.color,
+                        },
+                    );
+                }
+                self.ui_data.town_meshes.end_frame();
+
+                // LEGEND FOR THE GRADIENTS
+                for shape in gradient_legends(
+                    response.rect,
+                    &self.ui_data.selections,
+                    &ctx.style().visuals,
+                    1.0,
+                ) {
+                    Self::paint_shape(&painter, shape);
+                }
+
+                // LABELS
+                // the layers on top place their labels first
+                if canvas_data.zoom > LABEL_ZOOM {
+                    let mut labels: Vec<(&Town, LabelStyle, egui::Color32)> = Vec::new();
+                    for selection in self
+                        .ui_data
+                        .selections
                         .iter()
-                        .filter(|t| filter.town_in_viewport(t))
+                        .rev()
+                        .filter(|s| !s.is_hidden() && s.label.is_enabled())
                     {
-                        painter.circle_filled(
-                            canvas_data
-                                .world_to_screen(egui::vec2(town.x, town.y))
-                                .to_pos2(),
-                            1.0 + canvas_data.scale_world_to_screen(0.15),
-                            selection.color,
+                        let index = self.ui_data.town_indices.get(&selection.towns);
+                        labels.extend(
+                            visible(&selection.towns, &index, viewport)
+                                .into_iter()
+                                .map(|town| (town, selection.label, selection.color)),
                         );
                     }
+                    let changes_enabled = self.ui_data.baseline.is_some();
+                    for (settings, towns, enabled) in [
+                        (
+                            &self.ui_data.settings_changes,
+                            &visible_changed_towns,
+                            changes_enabled,
+                        ),
+                        (&self.ui_data.settings_ghosts, &visible_ghost_towns, true),
+                        (&self.ui_data.settings_all, &visible_towns_all, true),
+                    ] {
+                        if enabled && settings.enabled && settings.label.is_enabled() {
+                            labels.extend(
+                                towns
+                                    .iter()
+                                    .map(|town| (*town, settings.label, settings.color)),
+                            );
+                        }
+                    }
+                    Self::draw_labels(&painter, canvas_data, &labels);
+                }
+
+                // AREA SELECTION
+                if let Some(area) = &self.ui_data.area_selection {
+                    let stroke = egui::Stroke::new(1.5, ui.visuals().selection.stroke.color);
+                    let fill = ui.visuals().selection.bg_fill.linear_multiply(0.3);
+                    match area.shape {
+                        AreaShape::Rectangle => {
+                            let rect = area.bounds();
+                            painter.rect(
+                                egui::Rect::from_min_max(
+                                    canvas_data.world_to_screen(rect.min.to_vec2()).to_pos2(),
+                                    canvas_data.world_to_screen(rect.max.to_vec2()).to_pos2(),
+                                ),
+                                0.0,
+                                fill,
+                                stroke,
+                            );
+                        }
+                        AreaShape::Lasso => {
+                            let points: Vec<egui::Pos2> = area
+                                .points
+                                .iter()
+                                .map(|point| canvas_data.world_to_screen(*point).to_pos2())
+                                .collect();
+                            // the fill of egui is only correct for convex shapes, so the lasso is
+                            // only outlined
+                            if area.finished {
+                                painter.add(Shape::closed_line(points, stroke));
+                            } else {
+                                painter.add(Shape::line(points, stroke));
+                            }
+                        }
+                    }
+                }
+
+                // RULER
+                if let Some(ruler) = &self.ui_data.ruler {
+                    let stroke = egui::Stroke::new(2.0, ui.visuals().selection.stroke.color);
+                    let start = ruler.start.as_ref().map(|point| point.position);
+                    // follow the mouse until the end is placed
+                    let end = ruler.end.as_ref().map(|point| point.position).or_else(|| {
+                        response
+                            .hover_pos()
+                            .map(|mouse| canvas_data.screen_to_world(mouse.to_vec2()))
+                    });
+                    if let (Some(start), Some(end)) = (start, end) {
+                        let a = canvas_data.world_to_screen(start).to_pos2();
+                        let b = canvas_data.world_to_screen(end).to_pos2();
+                        painter.line_segment([a, b], stroke);
+                        painter.circle_filled(a, 3.0, stroke.color);
+                        painter.circle_filled(b, 3.0, stroke.color);
+                        painter.text(
+                            a + (b - a) / 2.0,
+                            egui::Align2::LEFT_BOTTOM,
+                            format!("{:.2}", (end - start).length()),
+                            egui::FontId::proportional(14.0),
+                            ui.visuals().strong_text_color(),
+                        );
+                    }
+                }
+
+                // CLICK ON A TOWN TO OPEN ITS PANEL, OR TO PLACE AN END OF THE RULER
+                if response.clicked() {
+                    if let Some(click) = response.interact_pointer_pos() {
+                        let position = canvas_data.screen_to_world(click.to_vec2()).to_pos2();
+                        // a few pixels, so towns can be hit at every zoom level
+                        let reach = canvas_data.scale_screen_to_world(8.0);
+                        let closest_town = towns_index
+                            .nearest(position, reach)
+                            .map(|(index, _)| &self.ui_data.all_towns[index]);
+                        if let Some(ruler) = &mut self.ui_data.ruler {
+                            // snap to the town, so the distance is the one the game uses
+                            ruler.click(closest_town.map_or(
+                                RulerPoint {
+                                    position: position.to_vec2(),
+                                    town: None,
+                                },
+                                |town| RulerPoint {
+                                    position: egui::vec2(town.x, town.y),
+                                    town: Some(town.name.clone()),
+                                },
+                            ));
+                        } else if let Some(town_id) = closest_town.map(|town| town.id) {
+                            let target = self
+                                .ui_data
+                                .town_panel
+                                .take()
+                                .map(|panel| panel.target)
+                                .unwrap_or_default();
+                            self.ui_data.town_panel = Some(TownPanel {
+                                town_id,
+                                details: TownPanelDetails::Loading,
+                                target,
+                            });
+                            self.channel_presenter_tx
+                                .send(MessageToModel::FetchTownDetails(town_id))
+                                .expect(
+                                    "Failed to send the FetchTownDetails Message to the backend",
+                                );
+                        }
+                    }
                 }
 
                 // POPUP WITH TOWN INFORMATION
@@ -171,23 +579,29 @@ impl View {
                         };
                         ui.label(format!("{position:?}"));
 
-                        if visible_towns_all.is_empty() {
-                            return;
-                        }
-                        let mut closest_town = visible_towns_all[0];
-                        let mut closest_distance =
-                            position.distance(egui::pos2(closest_town.x, closest_town.y));
-                        for town in &visible_towns_all {
-                            let distance = position.distance(egui::pos2(town.x, town.y));
-                            if distance < closest_distance {
-                                closest_distance = distance;
-                                closest_town = town;
+                        // towns and slots further away than this don't get a popup
+                        let reach = 1.5;
+                        let closest_town = towns_index.nearest(position, reach);
+                        if self.ui_data.settings_free_slots.enabled {
+                            if let Some((index, distance)) = slots_index.nearest(position, reach) {
+                                if closest_town.is_none_or(|(_, town)| distance < town) {
+                                    let slot = &self.ui_data.free_slots[index];
+                                    ui.label(t!(
+                                        "map.free_slot",
+                                        slot = slot.slot_number,
+                                        island_x = slot.island_x,
+                                        island_y = slot.island_y,
+                                        ocean = slot.ocean,
+                                    ));
+                                    return;
+                                }
                             }
                         }
 
-                        if closest_distance >= 1.5 {
+                        let Some((index, _)) = closest_town else {
                             return;
-                        }
+                        };
+                        let closest_town = &self.ui_data.all_towns[index];
                         ui.label(t!(
                             "map.hover",
                             name = closest_town.name,
@@ -195,6 +609,11 @@ impl View {
                             player = closest_town.player_name.as_deref().unwrap_or(""),
                             alliance = closest_town.alliance_name.as_deref().unwrap_or(""),
                         ));
+                        if let Some(last_growth) = &closest_town.player_last_growth {
+                            ui.label(t!("map.last_growth", date = last_growth));
+                        } else if let Some(hours) = closest_town.player_hours_inactive {
+                            ui.label(t!("map.no_growth", hours = hours));
+                        }
                     });
                 }
 
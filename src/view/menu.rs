@@ -1,12 +1,17 @@
 use super::{
-    preferences::{CacheSize, DarkModePref, Language, Preferences},
+    preferences::{CacheSize, DarkModePref, InactivityWindow, Language, Preferences},
+    ruler::Ruler,
     State, View,
 };
 use crate::{
+    bbcode::ListEntry,
     emptyselection::EmptyTownSelection,
     message::{MessageToModel, Progress},
+    selection::TownSelection,
     storage,
+    tms::{TmsFile, TmsReport},
 };
+use anyhow::Context;
 use arboard::Clipboard;
 use native_dialog::FileDialog;
 use rust_i18n::t;
@@ -14,6 +19,116 @@ use std::collections::BTreeMap;
 use strum::IntoEnumIterator;
 
 impl View {
+    /// Add the selections of .tms texts. If anything looks off, the user gets a report listing
+    /// the problems of every file.
+    fn import_tms(&mut self, texts: Vec<(String, anyhow::Result<String>)>) {
+        let mut reports = Vec::with_capacity(texts.len());
+        for (source, text) in texts {
+            let report = match text {
+                Ok(text) => {
+                    let existing_names: Vec<String> = self
+                        .ui_data
+                        .selections
+                        .iter()
+                        .map(|selection| selection.name.clone())
+                        .collect();
+                    let (selections, report) =
+                        TmsReport::read(source, &text, &self.ui_data.server_id, &existing_names);
+                    for town_selection in selections.iter().map(EmptyTownSelection::fill) {
+                        if !self.ui_data.selections.contains(&town_selection) {
+                            self.ui_data.selections.push(town_selection);
+                        }
+                    }
+                    report
+                }
+                Err(err) => TmsReport::failed(source, &err),
+            };
+            reports.push(report);
+        }
+        if reports.iter().any(|report| !report.is_clean()) {
+            self.ui_data.tms_reports = reports;
+        }
+    }
+
+    /// List the errors and warnings of the last import of .tms files
+    pub(crate) fn ui_tms_reports(&mut self, ctx: &egui::Context) {
+        if self.ui_data.tms_reports.is_empty() {
+            return;
+        }
+        let mut open = true;
+        let mut close_clicked = false;
+        egui::Window::new(t!("menu.import.tms_report_title"))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(400.0)
+                    .show(ui, |ui| {
+                        for report in &self.ui_data.tms_reports {
+                            ui.strong(&report.source);
+                            ui.label(t!("menu.import.imported", count = report.imported));
+                            for error in &report.errors {
+                                ui.colored_label(ui.visuals().error_fg_color, error);
+                            }
+                            for warning in &report.warnings {
+                                ui.colored_label(ui.visuals().warn_fg_color, warning);
+                            }
+                            ui.separator();
+                        }
+                    });
+                if ui.button(t!("menu.import.close")).clicked() {
+                    close_clicked = true;
+                }
+            });
+        if !open || close_clicked {
+            self.ui_data.tms_reports = Vec::new();
+        }
+    }
+
+    /// Ask the backend to turn a pasted list of towns, players and alliances into a selection
+    fn import_list(&mut self, name: String, text: &str) {
+        let entries = ListEntry::parse_list(text);
+        self.channel_presenter_tx
+            .send(MessageToModel::ImportList(name, entries))
+            .expect("Failed to send ImportList message to backend");
+    }
+
+    /// Tell the user which selection was created from an imported list and what was not found
+    pub(crate) fn ui_import_report(&mut self, ctx: &egui::Context) {
+        let Some(report) = &self.ui_data.import_report else {
+            return;
+        };
+        let mut open = true;
+        let mut close_clicked = false;
+        egui::Window::new(t!("menu.import.report_title"))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                match &report.selection_name {
+                    Some(name) => ui.label(t!("menu.import.created_selection", name = name)),
+                    None => ui.label(t!("menu.import.nothing_found")),
+                };
+                if !report.missing.is_empty() {
+                    ui.separator();
+                    ui.label(t!("menu.import.not_found", count = report.missing.len()));
+                    egui::ScrollArea::vertical()
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for entry in &report.missing {
+                                ui.label(format!("{entry}"));
+                            }
+                        });
+                }
+                ui.separator();
+                if ui.button(t!("menu.import.close")).clicked() {
+                    close_clicked = true;
+                }
+            });
+        if !open || close_clicked {
+            self.ui_data.import_report = None;
+        }
+    }
+
     #[allow(clippy::too_many_lines)] // UI Code, am I right, hahah
     #[allow(clippy::single_match)] // temporary, until we fix the error reporting and make it more user friendly
     pub(crate) fn ui_menu(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
@@ -30,13 +145,14 @@ impl View {
                         ui.menu_button(server, |ui| {
                             for saved_db in saved_dbs {
                                 if ui.button(format!("{saved_db}")).clicked() {
-                                    clicked_path = Some(saved_db.clone());
+                                    clicked_path = Some((server.clone(), saved_db.clone()));
                                     ui.close_menu();
                                 }
                             }
                         });
                     }
-                    if let Some(saved_db) = clicked_path {
+                    if let Some((server, saved_db)) = clicked_path {
+                        self.ui_data.server_id = server;
                         self.reload_server();
                         self.channel_presenter_tx
                             .send(MessageToModel::LoadDataFromFile(saved_db.path, ctx.clone()))
@@ -118,6 +234,21 @@ impl View {
 
                     ui.separator();
 
+                    ui.menu_button(t!("menu.preferences.inactivity_window"), |ui| {
+                        for window in InactivityWindow::iter() {
+                            let selected = self.ui_data.preferences.inactivity_window == window;
+                            if ui.selectable_label(selected, window.to_string()).clicked() {
+                                self.ui_data.preferences.inactivity_window = window;
+                                self.channel_presenter_tx
+                                    .send(MessageToModel::InactivityWindow(window))
+                                    .expect("Failed to send InactivityWindow message to backend");
+                                ui.close_menu();
+                            }
+                        }
+                    });
+
+                    ui.separator();
+
                     for language in Language::iter() {
                         if ui.button(language.to_string()).clicked() {
                             language.apply();
@@ -141,24 +272,12 @@ impl View {
                 ui.menu_button(t!("menu.import.title"), |ui| {
                     if ui.button(t!("menu.import.from_clipboard")).clicked() {
                         match Clipboard::new() {
-                            Ok(mut clipboard) => match clipboard.get_text() {
-                                Ok(text) => {
-                                    let result = EmptyTownSelection::try_from_str(&text);
-                                    match result {
-                                        Ok(town_selections) => {
-                                            for town_selection in town_selections.iter().map(EmptyTownSelection::fill) {
-                                                if !self.ui_data.selections.contains(&town_selection) {
-                                                    self.ui_data.selections.push(town_selection);
-                                                }
-                                            }
-                                        },
-                                        Err(_) => {/* TODO report any errors to the user*/},
-                                    }
-                                }
-                                Err(err) => {
-                                    eprintln!("Got a Clipboard, but failed to get text from it: {err}");
-                                }
-                            },
+                            Ok(mut clipboard) => {
+                                let text = clipboard
+                                    .get_text()
+                                    .context("Got a Clipboard, but failed to get text from it");
+                                self.import_tms(vec![(t!("menu.import.clipboard"), text)]);
+                            }
                             Err(err) => {
                                 eprintln!("Did not get the clipboard: {err}");
                             }
@@ -172,41 +291,88 @@ impl View {
                             .show_open_multiple_file();
                         match files_res {
                             Ok(files) => {
-                                let results = EmptyTownSelection::try_from_path(&files);
-                                for result in results{
-                                    match result {
-                                        Ok(town_selections) => {
-                                            for town_selection in town_selections.iter().map(EmptyTownSelection::fill) {
-                                                if !self.ui_data.selections.contains(&town_selection) {
-                                                    self.ui_data.selections.push(town_selection);
-                                                }
-                                            }
-                                        },
-                                        Err(_) => {/* TODO report any errors to the user*/},
-                                    }
+                                let texts = files
+                                    .iter()
+                                    .map(|file| {
+                                        let text = std::fs::read_to_string(file).with_context(|| {
+                                            t!("menu.import.tms.read_failed")
+                                        });
+                                        (file.display().to_string(), text)
+                                    })
+                                    .collect();
+                                self.import_tms(texts);
+                            }
+                            Err(err) => {
+                                eprintln!("Failed to open a file picker: {err}");
+                            }
+                        }
+                    }
+
+                    ui.separator();
+
+                    if ui.button(t!("menu.import.list_from_clipboard")).clicked() {
+                        match Clipboard::new() {
+                            Ok(mut clipboard) => match clipboard.get_text() {
+                                Ok(text) => self.import_list(t!("menu.import.list_name"), &text),
+                                Err(err) => {
+                                    eprintln!("Got a Clipboard, but failed to get text from it: {err}");
                                 }
+                            },
+                            Err(err) => {
+                                eprintln!("Did not get the clipboard: {err}");
                             }
+                        }
+                        ui.close_menu();
+                    }
+                    if ui.button(t!("menu.import.list_from_file")).clicked() {
+                        let file_res = FileDialog::new()
+                            .add_filter("Text", &["txt"])
+                            .show_open_single_file();
+                        match file_res {
+                            Ok(Some(file)) => match std::fs::read_to_string(&file) {
+                                Ok(text) => {
+                                    let name = file.file_stem().map_or(
+                                        t!("menu.import.list_name"),
+                                        |stem| stem.to_string_lossy().to_string(),
+                                    );
+                                    self.import_list(name, &text);
+                                }
+                                Err(err) => {
+                                    eprintln!(
+                                        "Failed to read content of file {}: {err}",
+                                        file.display()
+                                    );
+                                }
+                            },
+                            Ok(None) => { /* ignore, the user knowingly clicked cancel */ }
                             Err(err) => {
                                 eprintln!("Failed to open a file picker: {err}");
                             }
                         }
+                        ui.close_menu();
                     }
                 });
 
                 //////////////////////////////////////////////////////////////////////////////////
                 ui.menu_button(t!("menu.export.title"), |ui| {
+                    let selections = self
+                        .ui_data
+                        .selections
+                        .iter()
+                        .map(TownSelection::partial_clone)
+                        .collect();
+                    let tms = TmsFile::new(&self.ui_data.server_id, selections);
                     if ui.button(t!("menu.export.to_clipboard")).clicked() {
                         match Clipboard::new() {
                             Ok(mut clipboard) => {
-                                let selections_yaml = serde_yaml::to_string(&self.ui_data.selections);
-                                match selections_yaml {
+                                match tms.to_yaml() {
                                     Ok(valid_yaml) => {
                                         if let Err(err) = clipboard.set_text(valid_yaml) {
                                             eprintln!("Failed to write YAML to clipboard: {err}");
                                         }
                                     }
                                     Err(err) => {
-                                        eprintln!("Failed to convert the list of selections into Yaml: {err}");
+                                        eprintln!("{err:?}");
                                     }
                                 }
                             }
@@ -223,15 +389,14 @@ impl View {
                         match file_res {
                             Ok(file_opt) => {
                                 if let Some(file_path) = file_opt {
-                                    let selections_yaml = serde_yaml::to_string(&self.ui_data.selections);
-                                    match selections_yaml {
+                                    match tms.to_yaml() {
                                         Ok(valid_yaml) => {
                                             if let Err(err) = std::fs::write(&file_path, valid_yaml) {
-                                                eprintln!("Failed to write YAML to file ({file_path:?}) Error: {err:?}");
+                                                eprintln!("Failed to write YAML to file ({}) Error: {err:?}", file_path.display());
                                             }
                                         }
                                         Err(err) => {
-                                            eprintln!("Failed to convert the list of selections into Yaml: {err:?}");
+                                            eprintln!("{err:?}");
                                         }
                                     }
                                 }else {
@@ -243,7 +408,55 @@ impl View {
                             }
                         }
                     }
+
+                    ui.separator();
+
+                    ui.menu_button(t!("menu.export.share_code"), |ui| {
+                        let mut shared = None;
+                        if ui.button(t!("menu.export.share_code_all")).clicked() {
+                            shared = Some(tms.selections.clone());
+                        }
+                        ui.separator();
+                        for selection in &tms.selections {
+                            if ui.button(&selection.name).clicked() {
+                                shared = Some(vec![selection.clone()]);
+                            }
+                        }
+                        if let Some(selections) = shared {
+                            let code = TmsFile::new(&self.ui_data.server_id, selections).to_share_code();
+                            match (code, Clipboard::new()) {
+                                (Ok(code), Ok(mut clipboard)) => {
+                                    if let Err(err) = clipboard.set_text(code) {
+                                        eprintln!("Failed to write share code to clipboard: {err}");
+                                    }
+                                }
+                                (Err(err), _) => {
+                                    eprintln!("{err:?}");
+                                }
+                                (_, Err(err)) => {
+                                    eprintln!("Did not get the clipboard: {err}");
+                                }
+                            }
+                            ui.close_menu();
+                        }
+                    });
+
+                    ui.separator();
+
+                    if ui.button(t!("menu.image.open")).clicked() {
+                        self.ui_data.show_image_export = true;
+                        ui.close_menu();
+                    }
                 });
+
+                //////////////////////////////////////////////////////////////////////////////////
+                let mut ruler_active = self.ui_data.ruler.is_some();
+                if ui
+                    .toggle_value(&mut ruler_active, t!("menu.ruler"))
+                    .changed()
+                {
+                    self.ui_data.ruler = ruler_active.then(Ruler::default);
+                }
             });
         });
     }
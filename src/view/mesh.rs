@@ -0,0 +1,305 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Weak};
+
+use egui::epaint::{CircleShape, Tessellator};
+
+use crate::heatmap::Heatmap;
+use crate::selection::TownSelection;
+use crate::territory::Territory;
+use crate::town::Town;
+
+use super::data::CanvasData;
+
+/// Up to this zoom level every layer is drawn as one mesh. Further in only a few towns are
+/// visible, and they are drawn one by one.
+pub const MESH_ZOOM: f32 = 10.0;
+
+/// Everything a mesh depends on besides the towns themselves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct MeshKey {
+    zoom: u32,
+    radius: u32,
+    style: u64,
+    pixels_per_point: u32,
+    font_texture_size: [usize; 2],
+}
+
+#[derive(Clone)]
+struct CachedMesh {
+    towns: Weak<Vec<Town>>,
+    key: MeshKey,
+    /// in screen coordinates for a world offset of zero
+    mesh: Arc<egui::Mesh>,
+    drawn: bool,
+}
+
+/// The meshes of the town layers and selections. A mesh is built once for all towns of a layer
+/// and moved along when the map is dragged. It is built again when the towns, their colours or
+/// the zoom level change.
+#[derive(Clone, Default)]
+pub struct TownMeshes {
+    entries: Vec<CachedMesh>,
+}
+
+impl TownMeshes {
+    /// Draw the towns of a layer as filled circles. `style` identifies the colours, so the mesh is
+    /// rebuilt when it changes. `visible` are the towns in the viewport, which are drawn one by
+    /// one when the map is zoomed in too far for a mesh.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        painter: &egui::Painter,
+        canvas: &CanvasData,
+        towns: &Arc<Vec<Town>>,
+        visible: &[&Town],
+        radius: f32,
+        style: impl Hash,
+        color: impl Fn(&Town) -> egui::Color32,
+    ) {
+        if canvas.zoom > MESH_ZOOM {
+            for town in visible {
+                painter.circle_filled(
+                    canvas.world_to_screen(egui::vec2(town.x, town.y)).to_pos2(),
+                    radius,
+                    color(town),
+                );
+            }
+            return;
+        }
+        if towns.is_empty() {
+            return;
+        }
+
+        let ctx = painter.ctx();
+        let atlas = ctx.fonts(egui::text::Fonts::texture_atlas);
+        let mut hasher = DefaultHasher::new();
+        style.hash(&mut hasher);
+        let key = MeshKey {
+            zoom: canvas.zoom.to_bits(),
+            radius: radius.to_bits(),
+            style: hasher.finish(),
+            pixels_per_point: ctx.pixels_per_point().to_bits(),
+            font_texture_size: atlas.lock().size(),
+        };
+
+        let cached = self.entries.iter_mut().find(|entry| {
+            entry.key == key && std::ptr::eq(entry.towns.as_ptr(), Arc::as_ptr(towns))
+        });
+        let mesh = if let Some(entry) = cached {
+            entry.drawn = true;
+            Arc::clone(&entry.mesh)
+        } else {
+            // the same tessellation egui does for single circles, so both paths look the same.
+            // Small circles become textured quads of pre-rendered discs.
+            let mut tessellator = Tessellator::new(
+                ctx.pixels_per_point(),
+                ctx.tessellation_options(|options| *options),
+                key.font_texture_size,
+                atlas.lock().prepared_discs(),
+            );
+            let mut mesh = egui::Mesh::default();
+            for town in towns.iter() {
+                let center = canvas.scale_world_to_screen(egui::vec2(town.x, town.y));
+                let circle = CircleShape::filled(center.to_pos2(), radius, color(town));
+                tessellator.tessellate_circle(circle, &mut mesh);
+            }
+            let mesh = Arc::new(mesh);
+            self.entries.push(CachedMesh {
+                towns: Arc::downgrade(towns),
+                key,
+                mesh: Arc::clone(&mesh),
+                drawn: true,
+            });
+            mesh
+        };
+
+        let mut mesh = (*mesh).clone();
+        mesh.translate(-canvas.scale_world_to_screen(canvas.world_offset_px));
+        painter.add(egui::Shape::mesh(mesh));
+    }
+
+    /// Forget the meshes that were not drawn in this frame, e.g. of an old zoom level
+    pub fn end_frame(&mut self) {
+        self.entries.retain(|entry| entry.drawn);
+        for entry in &mut self.entries {
+            entry.drawn = false;
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CachedTexture {
+    heatmap: Weak<Heatmap>,
+    color: egui::Color32,
+    texture: egui::TextureHandle,
+    drawn: bool,
+}
+
+/// The textures of the heatmaps of the selections. A texture is uploaded once per heatmap and
+/// colour, and stretched over the world when it is drawn.
+#[derive(Clone, Default)]
+pub struct HeatmapTextures {
+    entries: Vec<CachedTexture>,
+}
+
+impl HeatmapTextures {
+    pub fn draw(
+        &mut self,
+        painter: &egui::Painter,
+        canvas: &CanvasData,
+        heatmap: &Arc<Heatmap>,
+        color: egui::Color32,
+    ) {
+        if heatmap.cells.is_empty() {
+            return;
+        }
+        let cached = self.entries.iter_mut().find(|entry| {
+            entry.color == color && std::ptr::eq(entry.heatmap.as_ptr(), Arc::as_ptr(heatmap))
+        });
+        let texture_id = if let Some(entry) = cached {
+            entry.drawn = true;
+            entry.texture.id()
+        } else {
+            let texture = painter.ctx().load_texture(
+                "heatmap",
+                heatmap.image(color),
+                egui::TextureOptions::LINEAR,
+            );
+            let texture_id = texture.id();
+            self.entries.push(CachedTexture {
+                heatmap: Arc::downgrade(heatmap),
+                color,
+                texture,
+                drawn: true,
+            });
+            texture_id
+        };
+
+        let world = egui::Rect::from_min_max(
+            canvas.world_to_screen(egui::vec2(0.0, 0.0)).to_pos2(),
+            canvas.world_to_screen(egui::vec2(1000.0, 1000.0)).to_pos2(),
+        );
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        painter.image(texture_id, world, uv, egui::Color32::WHITE);
+    }
+
+    /// Free the textures that were not drawn in this frame
+    pub fn end_frame(&mut self) {
+        self.entries.retain(|entry| entry.drawn);
+        for entry in &mut self.entries {
+            entry.drawn = false;
+        }
+    }
+}
+
+/// The texture of the alliance territories, uploaded again when the territory or the colours of
+/// the alliances change. The colours are only worked out again when the visible selections, their
+/// colours or the opacity change, as that goes through every town of the selections.
+#[derive(Clone, Default)]
+pub struct TerritoryTexture {
+    cached: Option<CachedTerritory>,
+}
+
+#[derive(Clone)]
+struct CachedTerritory {
+    territory: Weak<Territory>,
+    /// towns and colour of every visible selection, in order
+    selections: Vec<(Weak<Vec<Town>>, egui::Color32)>,
+    opacity: u8,
+    texture: egui::TextureHandle,
+}
+
+impl CachedTerritory {
+    fn matches(
+        &self,
+        territory: &Arc<Territory>,
+        selections: &[TownSelection],
+        opacity: u8,
+    ) -> bool {
+        let mut visible = selections.iter().filter(|s| !s.is_hidden());
+        self.opacity == opacity
+            && std::ptr::eq(self.territory.as_ptr(), Arc::as_ptr(territory))
+            && self
+                .selections
+                .iter()
+                .map(Some)
+                .chain(std::iter::once(None))
+                .all(|cached| match (cached, visible.next()) {
+                    (Some((towns, color)), Some(selection)) => {
+                        *color == selection.color
+                            && std::ptr::eq(towns.as_ptr(), Arc::as_ptr(&selection.towns))
+                    }
+                    (None, None) => true,
+                    _ => false,
+                })
+    }
+}
+
+impl TerritoryTexture {
+    /// Draw the regions stretched over the world, and the borders that lie in the viewport. The
+    /// alliances are coloured like the selections that contain their towns.
+    pub fn draw(
+        &mut self,
+        painter: &egui::Painter,
+        canvas: &CanvasData,
+        territory: &Arc<Territory>,
+        selections: &[TownSelection],
+        opacity: u8,
+        border: egui::Stroke,
+    ) {
+        if territory.side == 0 {
+            return;
+        }
+        let texture_id = match &self.cached {
+            Some(cached) if cached.matches(territory, selections, opacity) => cached.texture.id(),
+            _ => {
+                // sharp edges between the cells, the borders are drawn on top anyway
+                let texture = painter.ctx().load_texture(
+                    "territory",
+                    territory.image(&territory.colors(selections, opacity)),
+                    egui::TextureOptions::NEAREST,
+                );
+                let texture_id = texture.id();
+                self.cached = Some(CachedTerritory {
+                    territory: Arc::downgrade(territory),
+                    selections: selections
+                        .iter()
+                        .filter(|s| !s.is_hidden())
+                        .map(|s| (Arc::downgrade(&s.towns), s.color))
+                        .collect(),
+                    opacity,
+                    texture,
+                });
+                texture_id
+            }
+        };
+
+        let world = egui::Rect::from_min_max(
+            canvas.world_to_screen(egui::vec2(0.0, 0.0)).to_pos2(),
+            canvas.world_to_screen(egui::vec2(1000.0, 1000.0)).to_pos2(),
+        );
+        let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+        painter.image(texture_id, world, uv, egui::Color32::WHITE);
+
+        let viewport = egui::Rect::from_min_max(
+            canvas
+                .screen_to_world(painter.clip_rect().left_top().to_vec2())
+                .to_pos2(),
+            canvas
+                .screen_to_world(painter.clip_rect().right_bottom().to_vec2())
+                .to_pos2(),
+        );
+        for [a, b] in &territory.borders {
+            if viewport.intersects(egui::Rect::from_two_pos(*a, *b)) {
+                painter.line_segment(
+                    [
+                        canvas.world_to_screen(a.to_vec2()).to_pos2(),
+                        canvas.world_to_screen(b.to_vec2()).to_pos2(),
+                    ],
+                    border,
+                );
+            }
+        }
+    }
+}
@@ -1,22 +1,37 @@
+mod area;
 mod data;
+mod details;
 pub(crate) mod dropdownbox;
+pub(crate) mod groups;
+pub(crate) mod image;
 mod map;
 mod menu;
+mod mesh;
 pub(crate) mod preferences;
+mod ruler;
 mod selectable_label;
 mod sidepanel;
+pub(crate) mod table;
 
 use crate::emptyconstraint::EmptyConstraint;
 use crate::emptyselection::EmptyTownSelection;
+use crate::freeslot::Resource;
+use crate::gradient::Gradient;
 use crate::message::{MessageToModel, MessageToServer, MessageToView, Progress, Server};
 use crate::selection::{SelectionState, TownSelection};
-use crate::view::data::Data;
+use crate::territory::Territory;
+use crate::view::data::{Data, ImportReport, TownPanelDetails};
+use crate::view::ruler::Ruler;
 use eframe::Storage;
 use egui::{FontData, ProgressBar, RichText, Ui};
 use std::collections::HashSet;
 use std::sync::{mpsc, Arc};
 use std::thread;
 use std::time::Duration;
+use strum::IntoEnumIterator;
+
+/// Covers more characters than the default fonts of egui, e.g. Japanese player names
+pub const UNICODE_FONT: &[u8] = include_bytes!("../../NotoSansJP-Regular.ttf");
 
 #[derive(Clone, Copy)]
 pub enum Change {
@@ -64,7 +79,7 @@ impl View {
         let mut fonts = egui::FontDefinitions::default();
         fonts.font_data.insert(
             String::from("Custom Font"),
-            FontData::from_static(include_bytes!("../../NotoSansJP-Regular.ttf")),
+            FontData::from_static(UNICODE_FONT),
         );
         fonts
             .families
@@ -99,6 +114,11 @@ impl View {
                 re.ui_data.preferences.cache_size,
             ))
             .expect("Failed to send message to backend: MaxCacheSize");
+        re.channel_presenter_tx
+            .send(MessageToModel::InactivityWindow(
+                re.ui_data.preferences.inactivity_window,
+            ))
+            .expect("Failed to send message to backend: InactivityWindow");
 
         // TODO
         // self.channel_presenter_tx
@@ -147,6 +167,15 @@ impl View {
         self.ui_data = Data {
             all_towns: Arc::new(Vec::new()),
             ghost_towns: Arc::new(Vec::new()),
+            changed_towns: Arc::new(Vec::new()),
+            free_slots: Arc::new(Vec::new()),
+            islands: Arc::new(Vec::new()),
+            territory: Arc::new(Territory::default()),
+            town_panel: None,
+            area_selection: None,
+            // the measured towns may not exist on the new server
+            ruler: self.ui_data.ruler.as_ref().map(|_| Ruler::default()),
+            baseline: None,
             ..self.ui_data.clone()
         };
         // the selections are invalidated after the backend sends "got server"
@@ -221,6 +250,152 @@ impl View {
         }
     }
 
+    /// Pick the snapshot of the current server which the loaded data is compared against
+    fn ui_baseline_input(&mut self, ui: &mut Ui) {
+        let mut new_baseline = None;
+        ui.horizontal(|ui| {
+            ui.label(t!("sidepanel.baseline.label"));
+            let selected_text = self
+                .ui_data
+                .baseline
+                .as_ref()
+                .map_or(t!("sidepanel.baseline.none"), ToString::to_string);
+            egui::ComboBox::from_id_source("ComboBox Baseline")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    if ui
+                        .selectable_label(
+                            self.ui_data.baseline.is_none(),
+                            t!("sidepanel.baseline.none"),
+                        )
+                        .clicked()
+                    {
+                        new_baseline = Some(None);
+                    }
+                    let saved_dbs = self
+                        .ui_data
+                        .saved_db
+                        .get(&self.ui_data.server_id)
+                        .cloned()
+                        .unwrap_or_default();
+                    for saved_db in saved_dbs {
+                        let selected = self.ui_data.baseline.as_ref() == Some(&saved_db);
+                        if ui
+                            .selectable_label(selected, format!("{saved_db}"))
+                            .clicked()
+                        {
+                            new_baseline = Some(Some(saved_db));
+                        }
+                    }
+                });
+        });
+
+        if let Some(baseline) = new_baseline {
+            self.channel_presenter_tx
+                .send(MessageToModel::SetBaseline(
+                    baseline.as_ref().map(|saved_db| saved_db.path.clone()),
+                ))
+                .expect("Failed to send the SetBaseline Message to the backend");
+            self.ui_data.baseline = baseline;
+        }
+    }
+
+    /// Restrict the free slot layer to an ocean, an island or the island resources
+    fn ui_free_slot_filter(&mut self, ui: &mut Ui) {
+        let old_filter = self.ui_data.free_slot_filter.clone();
+        let filter = &mut self.ui_data.free_slot_filter;
+        let any = t!("sidepanel.free_slots.any");
+
+        egui::Grid::new("Grid Free Slot Filter").show(ui, |ui| {
+            ui.label(t!("sidepanel.free_slots.ocean"));
+            egui::ComboBox::from_id_source("ComboBox Free Slot Ocean")
+                .selected_text(filter.ocean.map_or(any.clone(), |ocean| format!("{ocean}")))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.ocean, None, &any);
+                    for ocean in 0..100 {
+                        ui.selectable_value(&mut filter.ocean, Some(ocean), format!("{ocean}"));
+                    }
+                });
+            ui.end_row();
+
+            ui.label(t!("sidepanel.free_slots.island"));
+            ui.add(
+                egui::TextEdit::singleline(&mut filter.island)
+                    .hint_text("475|512")
+                    .desired_width(80.0),
+            );
+            ui.end_row();
+
+            for (label, id, resource) in [
+                (
+                    t!("sidepanel.free_slots.ressource_plus"),
+                    "ComboBox Free Slot Resource Plus",
+                    &mut filter.ressource_plus,
+                ),
+                (
+                    t!("sidepanel.free_slots.ressource_minus"),
+                    "ComboBox Free Slot Resource Minus",
+                    &mut filter.ressource_minus,
+                ),
+            ] {
+                ui.label(label);
+                egui::ComboBox::from_id_source(id)
+                    .selected_text(resource.map_or(any.clone(), |r| r.to_string()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(resource, None, &any);
+                        for option in Resource::iter() {
+                            ui.selectable_value(resource, Some(option), option.to_string());
+                        }
+                    });
+                ui.end_row();
+            }
+        });
+
+        // incomplete island coordinates don't change the result, no need to ask the backend
+        if filter.ocean != old_filter.ocean
+            || filter.island() != old_filter.island()
+            || filter.ressource_plus != old_filter.ressource_plus
+            || filter.ressource_minus != old_filter.ressource_minus
+        {
+            self.channel_presenter_tx
+                .send(MessageToModel::FetchFreeSlots(filter.clone()))
+                .expect("Failed to send the FetchFreeSlots Message to the backend");
+        }
+    }
+
+    /// Ask the backend for a fresh list of towns for every selection
+    fn refresh_all_selections(&mut self) {
+        let all_selections: Vec<EmptyTownSelection> = self
+            .ui_data
+            .selections
+            .iter()
+            .map(TownSelection::partial_clone)
+            .collect();
+        for selection in &mut self.ui_data.selections {
+            selection.towns = Arc::new(Vec::new());
+            selection.refresh_self(&self.channel_presenter_tx, HashSet::new(), &all_selections);
+        }
+    }
+
+    /// Add a selection, renamed if its name is taken. Returns the name it ended up with.
+    fn add_selection(&mut self, mut selection: TownSelection) -> String {
+        // other selections reference this one by name, so it has to be unique
+        let base_name = selection.name.clone();
+        let mut counter = 2;
+        while self
+            .ui_data
+            .selections
+            .iter()
+            .any(|other| other.name == selection.name)
+        {
+            selection.name = format!("{base_name} ({counter})");
+            counter += 1;
+        }
+        let name = selection.name.clone();
+        self.ui_data.selections.push(selection);
+        name
+    }
+
     fn ui_uninitialized(
         &mut self,
         ctx: &egui::Context,
@@ -265,6 +440,14 @@ impl View {
     fn ui_init(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         self.ui_menu(ctx, frame);
         self.ui_sidepanel(ctx);
+        self.ui_group_windows(ctx);
+        self.ui_town_tables(ctx);
+        self.ui_image_export(ctx);
+        self.ui_town_panel(ctx);
+        self.ui_area_selection(ctx);
+        self.ui_ruler(ctx);
+        self.ui_import_report(ctx);
+        self.ui_tms_reports(ctx);
         self.ui_map(ctx);
     }
 }
@@ -312,25 +495,51 @@ impl eframe::App for View {
                     self.channel_presenter_tx
                         .send(MessageToModel::FetchGhosts)
                         .expect("Failed to send message to model: FetchGhosts");
+                    self.channel_presenter_tx
+                        .send(MessageToModel::FetchFreeSlots(
+                            self.ui_data.free_slot_filter.clone(),
+                        ))
+                        .expect("Failed to send message to model: FetchFreeSlots");
+                    self.channel_presenter_tx
+                        .send(MessageToModel::FetchIslands)
+                        .expect("Failed to send message to model: FetchIslands");
+                    if self.ui_data.settings_territory.enabled {
+                        self.channel_presenter_tx
+                            .send(MessageToModel::FetchTerritory(
+                                self.ui_data.settings_territory.radius,
+                            ))
+                            .expect("Failed to send message to model: FetchTerritory");
+                    }
 
                     // ensure the towns in the selection are fetched anew after loading the data from the server.
                     // If we don't do this the selection may become stale and show towns from server ab12 on a
                     // map that is otherwise pulled from server cd34
-                    let all_selections: Vec<EmptyTownSelection> = self
-                        .ui_data
-                        .selections
-                        .iter()
-                        .map(TownSelection::partial_clone)
-                        .collect();
-                    for selection in &mut self.ui_data.selections {
-                        selection.towns = Arc::new(Vec::new());
-                        selection.refresh_self(
-                            &self.channel_presenter_tx,
-                            HashSet::new(),
-                            &all_selections,
-                        );
+                    self.refresh_all_selections();
+                }
+                MessageToView::ImageExportProgress(share) => {
+                    // a late progress message must not restart a finished export
+                    if self.ui_data.image_export_progress.is_some() {
+                        self.ui_data.image_export_progress = Some(share);
                     }
                 }
+                MessageToView::ImageExported(result) => {
+                    self.ui_data.image_export_progress = None;
+                    self.ui_data.image_export_error = result.err().map(|err| format!("{err:#}"));
+                }
+                MessageToView::GotActivity => {
+                    // the town lists carry the activity of their players
+                    self.channel_presenter_tx
+                        .send(MessageToModel::FetchAll)
+                        .expect("Failed to send message to model: FetchAll");
+                    self.refresh_all_selections();
+                }
+                MessageToView::GotBaseline => {
+                    // every change constraint now has a different meaning
+                    self.channel_presenter_tx
+                        .send(MessageToModel::FetchChanges)
+                        .expect("Failed to send message to model: FetchChanges");
+                    self.refresh_all_selections();
+                }
                 MessageToView::TownListForSelection(selection, town_list) => {
                     self.ui_state = State::Show;
                     let optional_selection = self
@@ -341,6 +550,89 @@ impl eframe::App for View {
                     if let Some(selection) = optional_selection {
                         selection.towns = town_list;
                         selection.state = SelectionState::Finished;
+                        selection.error = None;
+                    } else {
+                        eprintln!("No existing selection found for {selection}");
+                    }
+                }
+                MessageToView::StatsForSelection(selection, stats) => {
+                    let optional_selection = self
+                        .ui_data
+                        .selections
+                        .iter_mut()
+                        .find(|element| *element == selection);
+                    if let Some(selection) = optional_selection {
+                        selection.stats = stats;
+                    } else {
+                        eprintln!("No existing selection found for {selection}");
+                    }
+                }
+                MessageToView::ImportedList(selection, missing) => {
+                    let selection_name =
+                        selection.map(|selection| self.add_selection(selection.fill()));
+                    self.ui_data.import_report = Some(ImportReport {
+                        selection_name,
+                        missing,
+                    });
+                }
+                MessageToView::GradientValuesForSelection(selection, values) => {
+                    let optional_selection = self
+                        .ui_data
+                        .selections
+                        .iter_mut()
+                        .find(|element| *element == selection);
+                    if let Some(selection_with_gradient) = optional_selection {
+                        // the gradient may have been changed while the backend was busy
+                        let attribute = |gradient: Option<Gradient>| gradient.map(|g| g.attribute);
+                        if attribute(selection_with_gradient.gradient)
+                            == attribute(selection.gradient)
+                        {
+                            selection_with_gradient.gradient_values = values;
+                        }
+                    } else {
+                        eprintln!("No existing selection found for {selection}");
+                    }
+                }
+                MessageToView::HeatmapForSelection(selection, heatmap) => {
+                    let optional_selection = self
+                        .ui_data
+                        .selections
+                        .iter_mut()
+                        .find(|element| *element == selection);
+                    if let Some(selection_with_heatmap) = optional_selection {
+                        // the heatmap may have been changed while the backend was busy
+                        if selection_with_heatmap.heatmap == selection.heatmap {
+                            selection_with_heatmap.heatmap_values = heatmap;
+                        }
+                    } else {
+                        eprintln!("No existing selection found for {selection}");
+                    }
+                }
+                MessageToView::SelectionFailed(selection, err) => {
+                    self.ui_state = State::Show;
+                    let optional_selection = self
+                        .ui_data
+                        .selections
+                        .iter_mut()
+                        .find(|element| *element == selection);
+                    if let Some(selection) = optional_selection {
+                        selection.state = SelectionState::Finished;
+                        selection.error = Some(format!("{err:#}"));
+                    } else {
+                        eprintln!("No existing selection found for {selection}: {err:?}");
+                    }
+                }
+                MessageToView::GroupsForSelection(selection, groups) => {
+                    let optional_selection = self
+                        .ui_data
+                        .selections
+                        .iter_mut()
+                        .find(|element| *element == selection);
+                    if let Some(selection_with_groups) = optional_selection {
+                        // the grouping may have been switched while the backend was busy
+                        if selection_with_groups.grouping == selection.grouping {
+                            selection_with_groups.groups = groups;
+                        }
                     } else {
                         eprintln!("No existing selection found for {selection}");
                     }
@@ -375,6 +667,36 @@ impl eframe::App for View {
                     self.ui_state = State::Show;
                     self.ui_data.ghost_towns = towns;
                 }
+                MessageToView::ChangedTowns(towns) => {
+                    self.ui_state = State::Show;
+                    self.ui_data.changed_towns = towns;
+                }
+                MessageToView::FreeSlots(slots) => {
+                    self.ui_state = State::Show;
+                    self.ui_data.free_slots = slots;
+                }
+                MessageToView::Islands(islands) => {
+                    self.ui_state = State::Show;
+                    self.ui_data.islands = islands;
+                }
+                MessageToView::Territory(radius, territory) => {
+                    // the radius may have been changed while the backend was busy
+                    if radius == self.ui_data.settings_territory.radius {
+                        self.ui_data.territory = territory;
+                    }
+                }
+                MessageToView::TownDetails(town_id, details) => {
+                    // the user may have clicked another town in the meantime
+                    if let Some(panel) = &mut self.ui_data.town_panel {
+                        if panel.town_id == town_id {
+                            panel.details = match details {
+                                Ok(Some(details)) => TownPanelDetails::Loaded(details),
+                                Ok(None) => TownPanelDetails::NotFound,
+                                Err(err) => TownPanelDetails::Failed(format!("{err:#}")),
+                            };
+                        }
+                    }
+                }
                 MessageToView::Loading(progress) => {
                     self.ui_state = State::Uninitialized(progress);
                 }
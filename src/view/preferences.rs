@@ -46,6 +46,38 @@ impl CacheSize {
     }
 }
 
+/// How far back in time we look for growth before a player counts as inactive
+#[derive(Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq, EnumIter)]
+pub enum InactivityWindow {
+    OneDay,
+    #[default]
+    ThreeDays,
+    OneWeek,
+    TwoWeeks,
+}
+
+impl ToString for InactivityWindow {
+    fn to_string(&self) -> String {
+        match self {
+            InactivityWindow::OneDay => t!("menu.preferences.inactivity_one_day"),
+            InactivityWindow::ThreeDays => t!("menu.preferences.inactivity_three_days"),
+            InactivityWindow::OneWeek => t!("menu.preferences.inactivity_one_week"),
+            InactivityWindow::TwoWeeks => t!("menu.preferences.inactivity_two_weeks"),
+        }
+    }
+}
+
+impl InactivityWindow {
+    pub fn value(self) -> time::Duration {
+        match self {
+            InactivityWindow::OneDay => time::Duration::days(1),
+            InactivityWindow::ThreeDays => time::Duration::days(3),
+            InactivityWindow::OneWeek => time::Duration::weeks(1),
+            InactivityWindow::TwoWeeks => time::Duration::weeks(2),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Serialize, Deserialize, Default, EnumIter)]
 pub enum Language {
     #[default]
@@ -85,6 +117,8 @@ pub struct Preferences {
     pub cache_size: CacheSize,
     #[serde(default)]
     pub language: Language,
+    #[serde(default)]
+    pub inactivity_window: InactivityWindow,
 }
 
 impl Default for Preferences {
@@ -94,6 +128,7 @@ impl Default for Preferences {
             auto_delete: AutoDeletePref::Eternity,
             cache_size: CacheSize::Normal,
             language: Language::EN,
+            inactivity_window: InactivityWindow::default(),
         }
     }
 }
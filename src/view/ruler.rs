@@ -0,0 +1,132 @@
+use strum::IntoEnumIterator;
+
+use crate::travel::{format_duration, TravelSettings, Unit};
+
+use super::View;
+
+/// One end of the ruler
+#[derive(Debug, Clone)]
+pub struct RulerPoint {
+    /// in world coordinates. The position of the town, if the point snapped to one.
+    pub position: egui::Vec2,
+    /// name of the town the point snapped to
+    pub town: Option<String>,
+}
+
+impl RulerPoint {
+    fn describe(&self) -> String {
+        self.town
+            .clone()
+            .unwrap_or_else(|| format!("{:.1}|{:.1}", self.position.x, self.position.y))
+    }
+}
+
+/// Measures the distance between two points on the map. While it exists, clicks on the map
+/// place its ends instead of opening the panel of a town.
+#[derive(Debug, Clone, Default)]
+pub struct Ruler {
+    pub start: Option<RulerPoint>,
+    pub end: Option<RulerPoint>,
+}
+
+impl Ruler {
+    /// The first click sets the start, the second one the end and the next one starts over
+    pub fn click(&mut self, point: RulerPoint) {
+        if self.start.is_none() || self.end.is_some() {
+            self.start = Some(point);
+            self.end = None;
+        } else {
+            self.end = Some(point);
+        }
+    }
+
+    /// in world units, once both ends are placed
+    pub fn distance(&self) -> Option<f32> {
+        match (&self.start, &self.end) {
+            (Some(start), Some(end)) => Some((end.position - start.position).length()),
+            _ => None,
+        }
+    }
+}
+
+impl View {
+    /// The window with the measured distance and the travel times of all units
+    pub(crate) fn ui_ruler(&mut self, ctx: &egui::Context) {
+        let Some(ruler) = &self.ui_data.ruler else {
+            return;
+        };
+        let settings = &mut self.ui_data.settings_travel;
+
+        let mut open = true;
+        egui::Window::new(t!("map.ruler.title"))
+            .id(egui::Id::new("Ruler Window"))
+            .open(&mut open)
+            .resizable(false)
+            .show(ctx, |ui| {
+                let (Some(start), Some(end), Some(distance)) =
+                    (&ruler.start, &ruler.end, ruler.distance())
+                else {
+                    ui.label(t!("map.ruler.hint"));
+                    return;
+                };
+                ui.label(t!(
+                    "map.ruler.distance",
+                    from = start.describe(),
+                    to = end.describe(),
+                    distance = format!("{distance:.2}"),
+                ));
+                ui.separator();
+
+                egui::Grid::new("Grid Ruler Settings").show(ui, |ui| {
+                    ui.label(t!("map.ruler.world_speed"));
+                    ui.add(
+                        egui::DragValue::new(&mut settings.world_speed)
+                            .clamp_range(0.1..=10.0)
+                            .speed(0.05)
+                            .max_decimals(2),
+                    );
+                    ui.end_row();
+                });
+                ui.checkbox(&mut settings.lighthouse, t!("map.ruler.lighthouse"));
+                ui.checkbox(&mut settings.meteorology, t!("map.ruler.meteorology"));
+                ui.separator();
+
+                egui::Grid::new("Grid Ruler Travel Times")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for unit in Unit::iter() {
+                            ui.label(unit.to_string());
+                            ui.label(
+                                settings
+                                    .travel_time(unit, distance)
+                                    .map_or_else(|| String::from("-"), format_duration),
+                            );
+                            ui.end_row();
+                        }
+                    });
+
+                ui.collapsing(t!("map.ruler.unit_speeds"), |ui| {
+                    egui::Grid::new("Grid Ruler Unit Speeds").show(ui, |ui| {
+                        for unit in Unit::iter() {
+                            ui.label(unit.to_string());
+                            let mut speed = settings.speed(unit);
+                            if ui
+                                .add(egui::DragValue::new(&mut speed).clamp_range(1..=50))
+                                .changed()
+                            {
+                                settings.unit_speeds.insert(unit, speed);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                    if ui.button(t!("map.ruler.reset_speeds")).clicked() {
+                        settings.unit_speeds = TravelSettings::default().unit_speeds;
+                    }
+                });
+            });
+
+        if !open {
+            self.ui_data.ruler = None;
+        }
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 use super::Change;
 use super::View;
 use crate::emptyselection::EmptyTownSelection;
+use crate::message::MessageToModel;
 use crate::selection::TownSelection;
 
 impl View {
@@ -12,6 +13,7 @@ impl View {
         egui::SidePanel::left("left panel").show(ctx, |ui| {
             ui.vertical(|ui| {
                 self.ui_server_input(ui, ctx);
+                self.ui_baseline_input(ui);
                 ui.label(t!(
                     "sidepanel.town_stats.total",
                     count = self.ui_data.all_towns.len()
@@ -20,17 +22,82 @@ impl View {
                     "sidepanel.town_stats.ghosts",
                     count = self.ui_data.ghost_towns.len()
                 ));
+                if self.ui_data.baseline.is_some() {
+                    ui.label(t!(
+                        "sidepanel.town_stats.changes",
+                        count = self.ui_data.changed_towns.len()
+                    ));
+                }
+                ui.label(t!(
+                    "sidepanel.town_stats.free_slots",
+                    count = self.ui_data.free_slots.len()
+                ));
                 ui.separator();
 
                 ui.horizontal(|ui| {
                     ui.checkbox(&mut self.ui_data.settings_all.enabled, "");
                     ui.label(t!("sidepanel.town_toggle.all"));
                     ui.color_edit_button_srgba(&mut self.ui_data.settings_all.color);
+                    self.ui_data.settings_all.label.ui(ui, "ComboBox Label all");
                 });
                 ui.horizontal(|ui| {
                     ui.checkbox(&mut self.ui_data.settings_ghosts.enabled, "");
                     ui.label(t!("sidepanel.town_toggle.ghosts"));
                     ui.color_edit_button_srgba(&mut self.ui_data.settings_ghosts.color);
+                    self.ui_data
+                        .settings_ghosts
+                        .label
+                        .ui(ui, "ComboBox Label ghosts");
+                });
+                if self.ui_data.baseline.is_some() {
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut self.ui_data.settings_changes.enabled, "");
+                        ui.label(t!("sidepanel.town_toggle.changes"));
+                        ui.color_edit_button_srgba(&mut self.ui_data.settings_changes.color);
+                        self.ui_data
+                            .settings_changes
+                            .label
+                            .ui(ui, "ComboBox Label changes");
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.ui_data.settings_free_slots.enabled, "");
+                    ui.label(t!("sidepanel.town_toggle.free_slots"));
+                    ui.color_edit_button_srgba(&mut self.ui_data.settings_free_slots.color);
+                });
+                if self.ui_data.settings_free_slots.enabled {
+                    self.ui_free_slot_filter(ui);
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.ui_data.settings_islands.enabled, "");
+                    ui.label(t!("sidepanel.town_toggle.islands"));
+                    ui.color_edit_button_srgba(&mut self.ui_data.settings_islands.land);
+                    ui.color_edit_button_srgba(&mut self.ui_data.settings_islands.sea);
+                });
+                ui.horizontal(|ui| {
+                    let old_settings = self.ui_data.settings_territory;
+                    let settings = &mut self.ui_data.settings_territory;
+                    ui.checkbox(&mut settings.enabled, "");
+                    ui.label(t!("sidepanel.town_toggle.territory"));
+                    let mut radius_changed = false;
+                    if settings.enabled {
+                        ui.label(t!("sidepanel.territory.radius"));
+                        let response = ui.add(
+                            egui::DragValue::new(&mut settings.radius)
+                                .clamp_range(5..=60)
+                                .speed(0.2),
+                        );
+                        // don't compute the territory for every step while the value is dragged
+                        radius_changed =
+                            response.drag_released() || (response.changed() && !response.dragged());
+                        ui.label(t!("sidepanel.territory.opacity"));
+                        ui.add(egui::DragValue::new(&mut settings.opacity).clamp_range(0..=255));
+                    }
+                    if (settings.enabled && !old_settings.enabled) || radius_changed {
+                        self.channel_presenter_tx
+                            .send(MessageToModel::FetchTerritory(settings.radius))
+                            .expect("Failed to send the FetchTerritory Message to the backend");
+                    }
                 });
                 ui.separator();
 
@@ -0,0 +1,292 @@
+use std::cmp::Ordering;
+
+use anyhow::Context;
+use arboard::Clipboard;
+use egui_extras::{Column, TableBuilder};
+use native_dialog::FileDialog;
+use serde::Serialize;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::town::Town;
+
+use super::View;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter)]
+pub enum TownColumn {
+    Name,
+    Id,
+    Player,
+    Alliance,
+    Points,
+    Coordinates,
+}
+
+impl ToString for TownColumn {
+    fn to_string(&self) -> String {
+        match self {
+            TownColumn::Name => t!("selection.table.column_name"),
+            TownColumn::Id => t!("selection.table.column_id"),
+            TownColumn::Player => t!("selection.table.column_player"),
+            TownColumn::Alliance => t!("selection.table.column_alliance"),
+            TownColumn::Points => t!("selection.table.column_points"),
+            TownColumn::Coordinates => t!("selection.table.column_coordinates"),
+        }
+    }
+}
+
+impl TownColumn {
+    fn compare(self, a: &Town, b: &Town) -> Ordering {
+        match self {
+            TownColumn::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            TownColumn::Id => a.id.cmp(&b.id),
+            TownColumn::Player => a
+                .player_name
+                .as_deref()
+                .map(str::to_lowercase)
+                .cmp(&b.player_name.as_deref().map(str::to_lowercase)),
+            TownColumn::Alliance => a
+                .alliance_name
+                .as_deref()
+                .map(str::to_lowercase)
+                .cmp(&b.alliance_name.as_deref().map(str::to_lowercase)),
+            TownColumn::Points => a.points.cmp(&b.points),
+            TownColumn::Coordinates => (a.island_x, a.island_y, a.slot_number).cmp(&(
+                b.island_x,
+                b.island_y,
+                b.slot_number,
+            )),
+        }
+    }
+}
+
+/// Which column the table of towns is sorted by. Only lives as long as the app runs.
+#[derive(Debug, Clone, Copy)]
+pub struct TownSort {
+    pub column: TownColumn,
+    pub descending: bool,
+}
+
+impl Default for TownSort {
+    fn default() -> Self {
+        Self {
+            column: TownColumn::Points,
+            descending: true,
+        }
+    }
+}
+
+/// A town as it is written to exported files
+#[derive(Serialize)]
+struct TownRow<'a> {
+    id: i32,
+    name: &'a str,
+    player: Option<&'a str>,
+    alliance: Option<&'a str>,
+    points: u16,
+    island_x: u16,
+    island_y: u16,
+    slot_number: u8,
+}
+
+impl<'a> From<&'a Town> for TownRow<'a> {
+    fn from(town: &'a Town) -> Self {
+        Self {
+            id: town.id,
+            name: &town.name,
+            player: town.player_name.as_deref(),
+            alliance: town.alliance_name.as_deref(),
+            points: town.points,
+            island_x: town.island_x,
+            island_y: town.island_y,
+            slot_number: town.slot_number,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, EnumIter)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    fn name(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Json => "JSON",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+
+    fn serialize(self, towns: &[&Town]) -> anyhow::Result<String> {
+        let rows = towns.iter().map(|town| TownRow::from(*town));
+        match self {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                for row in rows {
+                    writer
+                        .serialize(row)
+                        .context("Failed to write a town as CSV")?;
+                }
+                let bytes = writer
+                    .into_inner()
+                    .context("Failed to finish writing the CSV")?;
+                String::from_utf8(bytes).context("The CSV is not valid UTF-8")
+            }
+            ExportFormat::Json => serde_json::to_string_pretty(&rows.collect::<Vec<_>>())
+                .context("Failed to write the towns as JSON"),
+        }
+    }
+
+    fn to_clipboard(self, towns: &[&Town]) -> anyhow::Result<()> {
+        let text = self.serialize(towns)?;
+        Clipboard::new()
+            .context("Did not get the clipboard")?
+            .set_text(text)
+            .context("Failed to write the towns to the clipboard")
+    }
+
+    fn to_file(self, towns: &[&Town], file_name: &str) -> anyhow::Result<()> {
+        let file_opt = FileDialog::new()
+            .set_filename(file_name)
+            .add_filter(self.name(), &[self.extension()])
+            .show_save_single_file()
+            .context("Failed to open a file chooser")?;
+        let Some(file_path) = file_opt else {
+            // the user knowingly clicked cancel
+            return Ok(());
+        };
+        let text = self.serialize(towns)?;
+        std::fs::write(&file_path, text)
+            .with_context(|| format!("Failed to write the towns to {}", file_path.display()))
+    }
+}
+
+impl View {
+    /// One window for every selection that lists its towns in a sortable table
+    #[allow(clippy::too_many_lines)]
+    pub fn ui_town_tables(&mut self, ctx: &egui::Context) {
+        let mut center_on = None;
+        for (selection_index, selection) in self.ui_data.selections.iter_mut().enumerate() {
+            if !selection.show_table {
+                continue;
+            }
+
+            egui::Window::new(format!(
+                "{} - {}",
+                selection.name,
+                t!("selection.table.title")
+            ))
+            .id(egui::Id::new(format!(
+                "Town Table Window {selection_index}"
+            )))
+            .open(&mut selection.show_table)
+            .default_width(500.0)
+            .show(ctx, |ui| {
+                let mut rows: Vec<&Town> = selection.towns.iter().collect();
+                let sort = selection.town_sort;
+                rows.sort_by(|a, b| {
+                    let ordering = sort.column.compare(a, b);
+                    if sort.descending {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    for format in ExportFormat::iter() {
+                        let file_name = format!("{}.{}", selection.name, format.extension());
+                        ui.menu_button(format.name(), |ui| {
+                            let result = if ui.button(t!("menu.export.to_clipboard")).clicked() {
+                                ui.close_menu();
+                                Some(format.to_clipboard(&rows))
+                            } else if ui.button(t!("menu.export.to_file")).clicked() {
+                                ui.close_menu();
+                                Some(format.to_file(&rows, &file_name))
+                            } else {
+                                None
+                            };
+                            if let Some(result) = result {
+                                selection.table_error = result
+                                    .with_context(|| {
+                                        t!("selection.table.export_failed", name = selection.name)
+                                    })
+                                    .err()
+                                    .map(|err| format!("{err:#}"));
+                            }
+                        });
+                    }
+                });
+                if let Some(error) = &selection.table_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                ui.separator();
+
+                let row_height = ui.text_style_height(&egui::TextStyle::Body);
+                TableBuilder::new(ui)
+                    .striped(true)
+                    .columns(Column::auto().resizable(true), TownColumn::iter().count())
+                    .header(row_height * 1.5, |mut header| {
+                        for column in TownColumn::iter() {
+                            header.col(|ui| {
+                                let arrow = match (sort.column == column, sort.descending) {
+                                    (false, _) => "",
+                                    (true, true) => " ⏷",
+                                    (true, false) => " ⏶",
+                                };
+                                let text = format!("{}{arrow}", column.to_string());
+                                if ui.selectable_label(sort.column == column, text).clicked() {
+                                    selection.town_sort = if sort.column == column {
+                                        TownSort {
+                                            column,
+                                            descending: !sort.descending,
+                                        }
+                                    } else {
+                                        TownSort {
+                                            column,
+                                            descending: column == TownColumn::Points,
+                                        }
+                                    };
+                                }
+                            });
+                        }
+                    })
+                    .body(|body| {
+                        body.rows(row_height, rows.len(), |row_index, mut row| {
+                            let town = rows[row_index];
+                            let no_name = t!("selection.grouping.no_name");
+                            let cells = [
+                                town.name.clone(),
+                                format!("{}", town.id),
+                                town.player_name.clone().unwrap_or(no_name.clone()),
+                                town.alliance_name.clone().unwrap_or(no_name),
+                                format!("{}", town.points),
+                                format!("{}|{}", town.island_x, town.island_y),
+                            ];
+                            for text in cells {
+                                row.col(|ui| {
+                                    // the whole row is clickable and moves the map to the town
+                                    let label = egui::Label::new(text).sense(egui::Sense::click());
+                                    if ui.add(label).clicked() {
+                                        center_on = Some(egui::vec2(town.x, town.y));
+                                    }
+                                });
+                            }
+                        });
+                    });
+            });
+        }
+
+        if let (Some(target), Some(canvas)) = (center_on, self.ui_data.canvas.as_mut()) {
+            canvas.center_on = Some(target);
+        }
+    }
+}